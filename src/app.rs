@@ -10,8 +10,8 @@ use winit::{
 use std::time::Instant;
 
 pub use crate::{
-    grabber::HttpGrabber,
-    home::Home,
+    grabber::{GrabberConfig, HttpGrabber},
+    home::{Home, Languages},
     image::EncodableLayout,
     menu::{Container, UpdateDelta, Draw, Input, Menu, Poll, PositionHierarchy, Tile},
     renderer::Renderer,
@@ -29,6 +29,10 @@ pub struct App {
 
 impl App {
     pub async fn new() -> Result<App> {
+        App::with_grabber_config(GrabberConfig::default()).await
+    }
+
+    pub async fn with_grabber_config(grabber_config: GrabberConfig) -> Result<App> {
         let event_loop = EventLoop::new();
         let window_builder = WindowBuilder::new()
             .with_min_inner_size(LogicalSize::new(50.0, 50.0))
@@ -40,8 +44,9 @@ impl App {
 
         let mut menu = Menu::new();
         menu.set_position(&Vec3::new(0.0, 0.0, 0.0));
+        menu.set_scale_factor(renderer.context().scale_factor());
 
-        let http_grabber = HttpGrabber::new();
+        let http_grabber = HttpGrabber::with_config(grabber_config);
 
         Ok(App {
             event_loop,
@@ -52,6 +57,17 @@ impl App {
         })
     }
 
+    pub fn with_languages(mut self, languages: Languages) -> Self {
+        self.menu.set_languages(languages);
+        self
+    }
+
+    // Whether rows load later pages of their sets as focus nears their end, on by default.
+    pub fn with_paging(mut self, paging: bool) -> Self {
+        self.menu.set_paging(paging);
+        self
+    }
+
     pub fn run(self) -> Result<()> {
         let App {
             event_loop,
@@ -97,6 +113,8 @@ impl App {
 
                     if menu_consumed {
                         menu.set_render_details(&mut renderer);
+                        // Focus might have moved onto a tile that wants a bigger image now.
+                        done_polling = false;
                     }
 
                     if !renderer_consumed && !menu_consumed {
@@ -159,6 +177,8 @@ impl App {
                             } => {
                                 renderer.resize(**new_inner_size);
                                 renderer.set_scale_factor(*scale_factor);
+                                menu.set_scale_factor(*scale_factor);
+                                done_polling = false;
                             }
                             _ => {}
                         }
@@ -188,6 +208,10 @@ impl App {
                     // Maybe we should be conservative with this?
                     window.request_redraw();
                 }
+                Event::LoopDestroyed => {
+                    http_grabber.dump_stats();
+                    http_grabber.shutdown();
+                }
                 _ => {}
             }
         });
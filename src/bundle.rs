@@ -0,0 +1,418 @@
+// Self contained snapshot of everything the home screen needs, so the exact same catalogue can be
+// shown without any network.
+//
+// A bundle is just a directory with a `manifest.json` mapping urls onto files in `files/`.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::stream::{self, StreamExt};
+use uuid::Uuid;
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    grabber::{
+        self, disk_cache, strip_query, DownloadProgress, GrabError, GrabResult, RetryPolicy, Transport,
+        TransportRequest, TransportResponse,
+    },
+    home::{Home, Item, Meta, RefSet, Set},
+    menu::{
+        container::{refset_url, set_page_url},
+        menu::HOME_URL,
+    },
+};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+const FILES_DIRECTORY: &str = "files";
+// Images fetched at once while capturing.
+const CAPTURE_CONCURRENCY: usize = 8;
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Manifest {
+    pub home_url: String,
+    // Seconds since the unix epoch.
+    pub captured_at: u64,
+    pub entries: Vec<ManifestEntry>,
+    // Anything referenced that couldn't be grabbed, replaying these just gives a 404.
+    pub failures: Vec<CaptureFailure>,
+    // Later pages of sets were captured too, so replaying can ask for them.
+    #[serde(default)]
+    pub pages: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ManifestEntry {
+    pub url: String,
+    // Relative to the bundle directory.
+    pub file: String,
+    pub size: u64,
+    pub content_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CaptureFailure {
+    pub url: String,
+    pub error: String,
+}
+
+// Crawl the home page, every dynamic ref set it points at, and every tile image into `directory`. With
+// `paging` every later page of those sets is crawled as well, anything that sends the wrong page is
+// recorded as a failure.
+//
+// Only failing to grab the home page itself is an error, anything else just ends up in `failures`.
+pub async fn capture(
+    transport: &dyn Transport,
+    retry: &RetryPolicy,
+    directory: &Path,
+    paging: bool,
+) -> Result<Manifest> {
+    tokio::fs::create_dir_all(directory.join(FILES_DIRECTORY)).await?;
+
+    let mut manifest = Manifest {
+        home_url: HOME_URL.to_owned(),
+        captured_at: disk_cache::unix_now(),
+        ..Default::default()
+    };
+
+    let home_bytes = capture_url(transport, retry, directory, HOME_URL, &mut manifest).await?;
+    let home = serde_json::from_slice::<Home>(&home_bytes)?;
+
+    let mut items: Vec<Item> = Vec::new();
+    let collection = home
+        .data
+        .collection()
+        .ok_or_else(|| anyhow!("home page of unknown kind {}", home.data.kind()))?;
+    for container in &collection.containers {
+        let set = &container.set;
+        items.extend(set.items.iter().flatten().cloned());
+        if paging {
+            capture_pages(transport, retry, directory, set, set.set_id, &mut items, &mut manifest).await?;
+        }
+
+        let ref_id = match set.ref_id {
+            Some(ref_id) => ref_id,
+            None => continue,
+        };
+
+        let url = refset_url(&ref_id);
+        let refset = match capture_url(transport, retry, directory, &url, &mut manifest).await {
+            Ok(bytes) => serde_json::from_slice::<RefSet>(&bytes),
+            Err(_) => continue,
+        };
+
+        match refset {
+            Ok(refset) => match refset.data.set() {
+                Some(set) => {
+                    items.extend(set.items.iter().flatten().cloned());
+                    if paging {
+                        let set_id = set.set_id.or(Some(ref_id));
+                        capture_pages(transport, retry, directory, &set, set_id, &mut items, &mut manifest).await?;
+                    }
+                }
+                None => eprintln!("captured refset {} is of unknown kind {}", url, refset.data.kind()),
+            },
+            Err(err) => eprintln!("captured refset {} doesn't parse: {:?}", url, err),
+        }
+    }
+
+    // Sorted so the same catalogue always produces the same manifest.
+    let image_urls = items
+        .iter()
+        .flat_map(|item| item.image.tile.values())
+        .map(|image| image.details().url.clone())
+        .collect::<BTreeSet<_>>();
+
+    let captured = stream::iter(image_urls)
+        .map(|url| async move {
+            let request = TransportRequest::new(&url);
+            let response = grabber::fetch_with_retry(transport, retry, &request, &DownloadProgress::default()).await;
+            (url, response)
+        })
+        .buffer_unordered(CAPTURE_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+    for (url, response) in captured {
+        match response {
+            Ok(response) => {
+                write_entry(directory, &url, &response.body, response.headers.content_type, &mut manifest).await?;
+            }
+            Err(err) => record_failure(&url, &err, &mut manifest),
+        }
+    }
+
+    manifest.entries.sort_by(|a, b| a.url.cmp(&b.url));
+    manifest.failures.sort_by(|a, b| a.url.cmp(&b.url));
+    tokio::fs::write(directory.join(MANIFEST_FILE), serde_json::to_vec_pretty(&manifest)?).await?;
+
+    Ok(manifest)
+}
+
+// Every page of `set` after the one we have, stopping at the first that can't be grabbed or isn't the
+// page asked for. Anything that doesn't page sends the first one again, which is never saved.
+async fn capture_pages(
+    transport: &dyn Transport,
+    retry: &RetryPolicy,
+    directory: &Path,
+    set: &Set,
+    set_id: Option<Uuid>,
+    items: &mut Vec<Item>,
+    manifest: &mut Manifest,
+) -> Result<()> {
+    let set_id = match set_id {
+        Some(set_id) => set_id,
+        None => return Ok(()),
+    };
+
+    let mut meta = set.meta.clone();
+    while let Some(offset) = meta.as_ref().and_then(Meta::next_offset) {
+        let page_size = meta.as_ref().map_or(0, |meta| meta.page_size);
+        let url = set_page_url(&set_id, offset, page_size);
+        let request = TransportRequest::new(&url);
+        let response = match grabber::fetch_with_retry(transport, retry, &request, &DownloadProgress::default()).await {
+            Ok(response) => response,
+            Err(err) => {
+                record_failure(&url, &err, manifest);
+                return Ok(());
+            }
+        };
+
+        let page = serde_json::from_slice::<RefSet>(&response.body)
+            .ok()
+            .and_then(|page| Some(page.data.set()?.into_owned()))
+            .filter(|page| page.meta.as_ref().map(|meta| meta.offset) == Some(offset));
+        let page = match page {
+            Some(page) => page,
+            None => {
+                record_failure(&url, &format!("not the page of {} at offset {}", set_id, offset), manifest);
+                return Ok(());
+            }
+        };
+
+        write_entry(directory, &url, &response.body, response.headers.content_type, manifest).await?;
+        items.extend(page.items.iter().flatten().cloned());
+        manifest.pages = true;
+        meta = page.meta;
+    }
+
+    Ok(())
+}
+
+async fn capture_url(
+    transport: &dyn Transport,
+    retry: &RetryPolicy,
+    directory: &Path,
+    url: &str,
+    manifest: &mut Manifest,
+) -> Result<Bytes> {
+    let request = TransportRequest::new(url);
+    match grabber::fetch_with_retry(transport, retry, &request, &DownloadProgress::default()).await {
+        Ok(response) => {
+            write_entry(directory, url, &response.body, response.headers.content_type, manifest).await?;
+            Ok(response.body)
+        }
+        Err(err) => {
+            record_failure(url, &err, manifest);
+            Err(err.into())
+        }
+    }
+}
+
+async fn write_entry(
+    directory: &Path,
+    url: &str,
+    body: &Bytes,
+    content_type: Option<String>,
+    manifest: &mut Manifest,
+) -> Result<()> {
+    let extension = content_type
+        .as_deref()
+        .and_then(extension_for)
+        .unwrap_or("bin");
+    let file = format!("{}/{:016x}.{}", FILES_DIRECTORY, disk_cache::url_hash(url), extension);
+    tokio::fs::write(directory.join(&file), body).await?;
+
+    manifest.entries.push(ManifestEntry {
+        url: url.to_owned(),
+        file,
+        size: body.len() as u64,
+        content_type,
+    });
+    Ok(())
+}
+
+fn record_failure(url: &str, error: &dyn fmt::Display, manifest: &mut Manifest) {
+    eprintln!("failed to capture {}: {}", url, error);
+    manifest.failures.push(CaptureFailure {
+        url: url.to_owned(),
+        error: error.to_string(),
+    });
+}
+
+fn extension_for(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+    match mime.as_str() {
+        "application/json" => Some("json"),
+        "image/jpeg" => Some("jpg"),
+        "image/png" => Some("png"),
+        "image/webp" => Some("webp"),
+        _ => None,
+    }
+}
+
+// Replays a captured bundle, anything that wasn't captured is a 404.
+//
+// Urls that weren't captured exactly fall back to whatever was captured for the same url ignoring the
+// query string, so tiles asking for a different image size still get the captured one.
+#[derive(Debug, Clone)]
+pub struct BundleTransport {
+    directory: PathBuf,
+    manifest: Manifest,
+    entries: HashMap<String, ManifestEntry>,
+    // Url without the query string to the captured url.
+    without_query: HashMap<String, String>,
+}
+
+impl BundleTransport {
+    pub fn open<P: Into<PathBuf>>(directory: P) -> Result<Self> {
+        let directory = directory.into();
+        let manifest_path = directory.join(MANIFEST_FILE);
+        let manifest = std::fs::read(&manifest_path)
+            .map_err(|err| anyhow!("failed to read bundle manifest {:?}: {}", manifest_path, err))?;
+        let manifest = serde_json::from_slice::<Manifest>(&manifest)?;
+
+        let entries = manifest
+            .entries
+            .iter()
+            .map(|entry| (entry.url.clone(), entry.clone()))
+            .collect();
+        let without_query = manifest
+            .entries
+            .iter()
+            .map(|entry| (strip_query(&entry.url).to_owned(), entry.url.clone()))
+            .collect();
+
+        Ok(Self {
+            directory,
+            manifest,
+            entries,
+            without_query,
+        })
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    pub fn entry(&self, url: &str) -> Option<&ManifestEntry> {
+        self.entries.get(url).or_else(|| {
+            let captured = self.without_query.get(strip_query(url))?;
+            self.entries.get(captured)
+        })
+    }
+}
+
+#[async_trait]
+impl Transport for BundleTransport {
+    async fn fetch(&self, request: TransportRequest) -> GrabResult<TransportResponse> {
+        let entry = match self.entry(&request.url) {
+            Some(entry) => entry,
+            None => return Ok(TransportResponse::not_found()),
+        };
+
+        let path = self.directory.join(&entry.file);
+        let body = tokio::fs::read(&path)
+            .await
+            .map_err(|err| GrabError::Transport(format!("{:?}: {}", path, err)))?;
+
+        let mut response = TransportResponse::ok(Bytes::from(body));
+        if let Some(content_type) = &entry.content_type {
+            response = response.with_header("content-type", content_type);
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{capture, BundleTransport};
+    use crate::fixtures::{self, PagedTransport};
+    use crate::menu::container::{refset_url, set_page_url};
+    use crate::grabber::{strip_query, RetryPolicy, Transport, TransportRequest, TransportResponse};
+    use crate::menu::menu::HOME_URL;
+
+    #[tokio::test]
+    async fn capture_and_replay() {
+        let directory = std::env::temp_dir().join(format!("dss-bundle-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+
+        let image_urls = fixtures::image_urls();
+        let missing = image_urls[0].clone();
+        let mut transport = fixtures::memory_transport();
+        transport.insert_response(strip_query(&missing), TransportResponse::not_found());
+
+        let manifest = capture(&transport, &RetryPolicy::never(), &directory, false)
+            .await
+            .expect("capture bundle");
+        // Home page, both ref sets, and every image but the missing one.
+        assert_eq!(manifest.entries.len(), 3 + image_urls.len() - 1);
+        assert_eq!(manifest.failures.len(), 1);
+        assert_eq!(manifest.failures[0].url, missing);
+
+        let bundle = BundleTransport::open(&directory).expect("open bundle");
+        let request = TransportRequest::new;
+
+        let home = bundle.fetch(request(HOME_URL)).await.expect("replay home");
+        assert_eq!(home.body, fixtures::fixture_bytes("home.json"));
+        let image = bundle.fetch(request(&image_urls[1])).await.expect("replay image");
+        assert_eq!(image.body, fixtures::png_bytes());
+        assert_eq!(bundle.fetch(request(&missing)).await.expect("replay missing").status, 404);
+
+        let resized = image_urls[1].replace("width=500", "width=384");
+        let image = bundle.fetch(request(&resized)).await.expect("replay resized image");
+        assert_eq!(image.body, fixtures::png_bytes());
+        assert!(!bundle.manifest().pages);
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    // Later pages get captured from something that pages, and anything sending the wrong page is
+    // recorded as a failure instead.
+    #[tokio::test]
+    async fn captures_later_pages() {
+        let directory = std::env::temp_dir().join(format!("dss-bundle-pages-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let ref_id = uuid::Uuid::parse_str("bd1bfb9a-bbf7-43a0-ac5e-3e3889d7224d").expect("ref id");
+
+        let transport = PagedTransport::new(fixtures::memory_transport(), &ref_id, 8, 3);
+        let manifest = capture(&transport, &RetryPolicy::never(), &directory, true)
+            .await
+            .expect("capture bundle");
+        assert!(manifest.pages);
+        assert!(manifest.failures.is_empty());
+
+        let bundle = BundleTransport::open(&directory).expect("open bundle");
+        for offset in &[3, 6] {
+            let page = bundle
+                .fetch(TransportRequest::new(&set_page_url(&ref_id, *offset, 3)))
+                .await
+                .expect("replay page");
+            assert_eq!(page.body, transport.page(*offset, 3));
+        }
+
+        let first_page = transport.page(0, 3);
+        let mut transport = fixtures::memory_transport();
+        transport.insert(&refset_url(&ref_id), first_page);
+        let manifest = capture(&transport, &RetryPolicy::never(), &directory, true)
+            .await
+            .expect("capture bundle");
+        assert!(!manifest.pages);
+        assert_eq!(manifest.failures.len(), 1);
+        assert_eq!(manifest.failures[0].url, set_page_url(&ref_id, 3, 3));
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+}
@@ -0,0 +1,269 @@
+// Every title on the home page and the sets it loads, by content id, so the same programme showing up
+// in several rows is known to be one thing.
+//
+// Rows are numbered the same as the home page containers, positions within a row count every item of
+// the set across all of its pages.
+
+use uuid::Uuid;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::home::{Home, Item, Languages, RefSet, Set};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Appearance {
+    pub row: usize,
+    pub position: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct CatalogueEntry {
+    // First copy seen, every other row showing the title shares it.
+    pub item: Arc<Item>,
+    pub appearances: Vec<Appearance>,
+}
+
+impl CatalogueEntry {
+    // Rows the title is in, in order and without repeats.
+    pub fn rows(&self) -> Vec<usize> {
+        let mut rows = self.appearances.iter().map(|appearance| appearance.row).collect::<Vec<_>>();
+        rows.sort_unstable();
+        rows.dedup();
+        rows
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Catalogue {
+    entries: HashMap<Uuid, CatalogueEntry>,
+    // Content ids in the order they were first seen.
+    order: Vec<Uuid>,
+    // Series id to the content ids of everything from it.
+    series: HashMap<Uuid, Vec<Uuid>>,
+    // Items without a content id, which can't be matched up with anything.
+    unindexed: usize,
+}
+
+impl Catalogue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Home page rows along with whichever of `refsets` fill in the ones that are only set refs.
+    pub fn from_home(home: &Home, refsets: &[RefSet]) -> Self {
+        let mut catalogue = Self::new();
+        let collection = match home.data.collection() {
+            Some(collection) => collection,
+            None => return catalogue,
+        };
+
+        let refsets = refsets
+            .iter()
+            .filter_map(|refset| refset.data.set())
+            .filter_map(|set| Some((set.set_id?, set)))
+            .collect::<HashMap<_, _>>();
+
+        for (row, container) in collection.containers.iter().enumerate() {
+            catalogue.add_set(row, &container.set);
+            if let Some(set) = container.set.ref_id.and_then(|ref_id| refsets.get(&ref_id)) {
+                catalogue.add_set(row, set);
+            }
+        }
+
+        catalogue
+    }
+
+    // A page of a set, placed by the offset in its meta.
+    pub fn add_set(&mut self, row: usize, set: &Set) {
+        let offset = set.meta.as_ref().map_or(0, |meta| meta.offset as usize);
+        for (index, item) in set.items.iter().flatten().enumerate() {
+            self.add_item(row, offset + index, item);
+        }
+    }
+
+    // Returns the shared copy of the item, `None` if it has no content id to index it by.
+    pub fn add_item(&mut self, row: usize, position: usize, item: &Item) -> Option<Arc<Item>> {
+        self.insert(row, position, item, || Arc::new(item.clone()))
+    }
+
+    // Same as `add_item`, but a title seen for the first time keeps `item` itself instead of a copy.
+    pub fn add_shared_item(&mut self, row: usize, position: usize, item: &Arc<Item>) -> Option<Arc<Item>> {
+        self.insert(row, position, item, || item.clone())
+    }
+
+    fn insert<F>(&mut self, row: usize, position: usize, item: &Item, share: F) -> Option<Arc<Item>>
+    where
+        F: FnOnce() -> Arc<Item>,
+    {
+        let content_id = match item.content_id {
+            Some(content_id) => content_id,
+            None => {
+                self.unindexed += 1;
+                return None;
+            }
+        };
+
+        let appearance = Appearance { row, position };
+        let entry = match self.entries.get_mut(&content_id) {
+            Some(entry) => entry,
+            None => {
+                if let Some(series_id) = item.series_id {
+                    self.series.entry(series_id).or_default().push(content_id);
+                }
+                self.order.push(content_id);
+                self.entries.entry(content_id).or_insert(CatalogueEntry {
+                    item: share(),
+                    appearances: Vec::new(),
+                })
+            }
+        };
+
+        // The same page can be loaded again, that isn't another appearance.
+        if !entry.appearances.contains(&appearance) {
+            entry.appearances.push(appearance);
+        }
+        Some(entry.item.clone())
+    }
+
+    pub fn get(&self, content_id: &Uuid) -> Option<&CatalogueEntry> {
+        self.entries.get(content_id)
+    }
+
+    pub fn rows_for(&self, content_id: &Uuid) -> Vec<usize> {
+        self.get(content_id).map(CatalogueEntry::rows).unwrap_or_default()
+    }
+
+    // Everything from a series, in the order it was first seen.
+    pub fn series(&self, series_id: &Uuid) -> Vec<&CatalogueEntry> {
+        self.series
+            .get(series_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|content_id| self.entries.get(content_id))
+            .collect()
+    }
+
+    // Titles containing `query` in any language, ignoring case, best match for `languages` first.
+    pub fn search(&self, query: &str, languages: &Languages) -> Vec<&CatalogueEntry> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let mut matches = self
+            .iter()
+            .filter_map(|entry| {
+                let title = &entry.item.text.title.full;
+                let preferred = title.localized(languages).content.to_lowercase().contains(&query);
+                let any = std::iter::once(&title.text().default)
+                    .chain(title.text().localized.values())
+                    .any(|details| details.content.to_lowercase().contains(&query));
+                any.then(|| (!preferred, entry))
+            })
+            .collect::<Vec<_>>();
+        // Stable, so first seen order is kept within each.
+        matches.sort_by_key(|(other_language, _)| *other_language);
+        matches.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    // In the order they were first seen.
+    pub fn iter(&self) -> impl Iterator<Item = &CatalogueEntry> {
+        self.order.iter().filter_map(move |content_id| self.entries.get(content_id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn unindexed(&self) -> usize {
+        self.unindexed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Appearance, Catalogue};
+    use crate::fixtures;
+    use crate::home::Languages;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    fn content_id(id: u128) -> Uuid {
+        Uuid::from_u128(id)
+    }
+
+    #[test]
+    fn indexes_home_and_sets() {
+        let catalogue = Catalogue::from_home(&fixtures::home(), &fixtures::refsets());
+        assert_eq!(catalogue.len(), 6);
+        assert_eq!(catalogue.unindexed(), 0);
+
+        let mandalorian = catalogue.get(&content_id(0x1001)).expect("mandalorian");
+        assert_eq!(mandalorian.item.title(), "The Mandalorian");
+        assert_eq!(mandalorian.rows(), vec![0, 2]);
+        assert_eq!(catalogue.rows_for(&content_id(0x1002)), vec![0, 1]);
+        assert_eq!(catalogue.rows_for(&content_id(0x1005)), vec![1]);
+        assert!(catalogue.rows_for(&content_id(0xffff)).is_empty());
+
+        let series_id = mandalorian.item.series_id.expect("series id");
+        let series = catalogue.series(&series_id);
+        assert_eq!(series.len(), 1);
+        assert!(Arc::ptr_eq(&series[0].item, &mandalorian.item));
+
+        let titles = catalogue.iter().map(|entry| entry.item.title()).collect::<Vec<_>>();
+        assert_eq!(titles[..3], ["The Mandalorian", "Soul", "Luca"]);
+    }
+
+    #[test]
+    fn shares_duplicates() {
+        let home = fixtures::home();
+        let collection = home.data.collection().expect("collection");
+        let item = &collection.containers[0].set.items.as_ref().expect("items")[0];
+
+        let mut catalogue = Catalogue::new();
+        let first = catalogue.add_item(0, 0, item).expect("indexed");
+        let second = catalogue.add_item(3, 7, item).expect("indexed");
+        assert!(Arc::ptr_eq(&first, &second));
+        // Seeing the same page again doesn't count twice.
+        catalogue.add_item(3, 7, item);
+
+        let entry = catalogue.get(&item.content_id.expect("content id")).expect("entry");
+        assert_eq!(
+            entry.appearances,
+            vec![Appearance { row: 0, position: 0 }, Appearance { row: 3, position: 7 }]
+        );
+
+        let shared = Arc::new(item.clone());
+        assert!(Arc::ptr_eq(&catalogue.add_shared_item(5, 0, &shared).expect("indexed"), &first));
+        let mut other = item.clone();
+        other.content_id = Some(content_id(0xbeef));
+        let other = Arc::new(other);
+        assert!(Arc::ptr_eq(&catalogue.add_shared_item(5, 1, &other).expect("indexed"), &other));
+
+        let mut anonymous = item.clone();
+        anonymous.content_id = None;
+        assert!(catalogue.add_item(0, 1, &anonymous).is_none());
+        assert_eq!(catalogue.unindexed(), 1);
+    }
+
+    #[test]
+    fn search() {
+        let catalogue = Catalogue::from_home(&fixtures::home(), &fixtures::refsets());
+        let titles = |query: &str| {
+            catalogue
+                .search(query, &Languages::default())
+                .iter()
+                .map(|entry| entry.item.title().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(titles("SOUL"), vec!["Soul"]);
+        assert_eq!(titles("an"), vec!["The Mandalorian", "WandaVision", "Encanto"]);
+        assert!(titles("  ").is_empty());
+    }
+}
@@ -0,0 +1,282 @@
+// What the menu would show, worked out without a window: the home page and every set it refers to go
+// through the grabber and into the same rows and tiles the menu builds, but no images get downloaded.
+//
+// Rows only load the pages the menu would before anything is focused, the rest are counted as skipped.
+// Same as the menu, later pages are only asked for with `paging`.
+
+use uuid::Uuid;
+
+use std::fmt::{self, Write as _};
+use std::sync::Arc;
+
+use crate::{
+    grabber::{Decode, GrabResult, HttpGrabber, Payload, Priority},
+    home::{Languages, RefSet},
+    menu::{container::refset_url, menu::HOME_URL, Container, Tile},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Dump {
+    pub home_url: String,
+    pub rows: Vec<RowDump>,
+    // Why the home page couldn't be shown at all, there are no rows when this is set.
+    pub failure: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RowDump {
+    pub title: String,
+    pub style: String,
+    pub layout: String,
+    pub aspect_ratio: f32,
+    pub ref_id: Option<Uuid>,
+    pub tiles: Vec<TileDump>,
+    // Anything the row would have shown but doesn't, and why.
+    pub skipped: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TileDump {
+    pub title: String,
+    pub content_id: Option<Uuid>,
+    // What the tile would ask for at its unfocused size, `None` for title cards.
+    pub image_url: Option<String>,
+    // Why the tile isn't showing art in the shape of the row, if it isn't.
+    pub fallback: Option<String>,
+}
+
+impl TileDump {
+    fn from_tile(tile: &Tile, aspect_ratio: f32) -> Self {
+        let fallback = if tile.is_title_card() {
+            Some("no art at all, showing a title card".to_owned())
+        } else {
+            tile.crop_from()
+                .map(|crop_from| format!("no {:.2} art, cropped from {:.2}", aspect_ratio, crop_from))
+        };
+
+        Self {
+            title: tile.title().to_owned(),
+            content_id: tile.content_id(),
+            image_url: (!tile.is_title_card()).then(|| tile.details().sized_url(tile.wanted_width())),
+            fallback,
+        }
+    }
+}
+
+impl Dump {
+    // Loads everything through `grabber`, so this works the same against the live api, a bundle or
+    // anything else there is a transport for.
+    pub async fn load(grabber: &mut HttpGrabber, languages: &Languages, paging: bool) -> Dump {
+        let mut dump = Dump {
+            home_url: HOME_URL.to_owned(),
+            rows: Vec::new(),
+            failure: None,
+        };
+
+        let request = grabber.submit_decoded(HOME_URL.to_owned(), Priority::Focused, Decode::Home);
+        let home = match grabber.take(request).await.and_then(Payload::into_home) {
+            Ok(home) => home,
+            Err(err) => {
+                dump.failure = Some(format!("couldn't load home page: {}", err));
+                return dump;
+            }
+        };
+        let collection = match home.data.collection() {
+            Some(collection) => collection,
+            None => {
+                dump.failure = Some(format!("unsupported home page kind {}", home.data.kind()));
+                return dump;
+            }
+        };
+
+        for container_ref in &collection.containers {
+            let mut container = Container::from_home(container_ref);
+            container.set_languages(languages);
+            container.set_paging(paging);
+            let mut skipped = Vec::new();
+
+            if let Some(ref_id) = container.ref_id() {
+                let url = refset_url(&ref_id);
+                match take_refset(grabber, &url).await {
+                    Ok(refset) => {
+                        if refset.data.set().is_none() {
+                            skipped.push(format!("set {} is of unknown kind {}", url, refset.data.kind()));
+                        }
+                        container.construct_refset(&refset);
+                    }
+                    Err(err) => skipped.push(format!("couldn't load set {}: {}", url, err)),
+                }
+            }
+
+            while container.near_end() {
+                let url = match container.next_page_url() {
+                    Some(url) => url,
+                    None => break,
+                };
+                let added = take_refset(grabber, &url)
+                    .await
+                    .map_err(|err| err.to_string())
+                    .and_then(|page| container.add_page(&page));
+                if let Err(reason) = added {
+                    skipped.push(format!("couldn't load page {}: {}", url, reason));
+                    break;
+                }
+            }
+
+            if let Some(meta) = container.meta() {
+                let remaining = (meta.hits as usize).saturating_sub(container.item_count());
+                if remaining > 0 && container.paging() {
+                    skipped.push(format!("{} more items on later pages, loaded as focus nears the end", remaining));
+                } else if remaining > 0 {
+                    skipped.push(format!("{} more items on later pages, not loaded with paging off", remaining));
+                }
+            }
+
+            let aspect_ratio = container.aspect_ratio();
+            dump.rows.push(RowDump {
+                title: container.title().to_owned(),
+                style: container_ref.style.clone(),
+                layout: format!("{:?}", container.layout()).to_lowercase(),
+                aspect_ratio,
+                ref_id: container.ref_id(),
+                tiles: container
+                    .tiles
+                    .iter()
+                    .map(|tile| TileDump::from_tile(tile, aspect_ratio))
+                    .collect(),
+                skipped,
+            });
+        }
+
+        dump
+    }
+
+    // Indented tree of rows and their tiles, easier to skim than the json.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        // Writing to a string can't fail.
+        let _ = self.write_text(&mut text);
+        text
+    }
+
+    fn write_text(&self, out: &mut String) -> fmt::Result {
+        writeln!(out, "{}", self.home_url)?;
+        if let Some(failure) = &self.failure {
+            writeln!(out, "  failed: {}", failure)?;
+        }
+
+        for row in &self.rows {
+            writeln!(
+                out,
+                "  {} ({}, {} layout, {:.2})",
+                row.title, row.style, row.layout, row.aspect_ratio
+            )?;
+            for tile in &row.tiles {
+                writeln!(out, "    {}", tile.title)?;
+                if let Some(image_url) = &tile.image_url {
+                    writeln!(out, "      {}", image_url)?;
+                }
+                if let Some(fallback) = &tile.fallback {
+                    writeln!(out, "      {}", fallback)?;
+                }
+            }
+            for skipped in &row.skipped {
+                writeln!(out, "    skipped: {}", skipped)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn take_refset(grabber: &mut HttpGrabber, url: &str) -> GrabResult<Arc<RefSet>> {
+    let request = grabber.submit_decoded(url.to_owned(), Priority::Focused, Decode::RefSet);
+    grabber.take(request).await.and_then(Payload::into_refset)
+}
+
+#[cfg(test)]
+mod test {
+    use super::Dump;
+    use crate::fixtures::{self, PagedTransport};
+    use crate::grabber::{GrabberConfig, HttpGrabber, RetryPolicy, Transport};
+    use crate::home::Languages;
+    use crate::menu::container::refset_url;
+    use std::sync::Arc;
+
+    fn grabber(transport: Arc<dyn Transport>) -> HttpGrabber {
+        HttpGrabber::with_config(GrabberConfig {
+            transport,
+            disk_cache: None,
+            retry: RetryPolicy::never(),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn dumps_rows() {
+        let transport = fixtures::memory_transport();
+        let mut grabber = grabber(Arc::new(transport));
+        let dump = Dump::load(&mut grabber, &Languages::default(), false).await;
+
+        assert!(dump.failure.is_none());
+        let tile_counts = dump.rows.iter().map(|row| row.tiles.len()).collect::<Vec<_>>();
+        assert_eq!(tile_counts, vec![3, 3, 2]);
+        assert_eq!(dump.rows[2].layout, "trending");
+        // Nothing downloads images.
+        assert!(!grabber.stats().by_kind.contains_key("image"));
+
+        let luca = &dump.rows[0].tiles[2];
+        assert_eq!(luca.title, "Luca");
+        assert_eq!(luca.fallback.as_deref(), Some("no 1.78 art, cropped from 0.71"));
+        assert!(luca.image_url.as_deref().map_or(false, |url| url.ends_with("&width=384")));
+
+        let json = serde_json::to_value(&dump).expect("dump json");
+        assert_eq!(json["rows"][0]["tiles"][0]["title"], "The Mandalorian");
+
+        let text = dump.to_text();
+        assert!(text.contains("    Luca\n"));
+        assert!(text.contains("      no 1.78 art, cropped from 0.71\n"));
+    }
+
+    #[tokio::test]
+    async fn reports_what_was_skipped() {
+        let ref_id = uuid::Uuid::parse_str("bd1bfb9a-bbf7-43a0-ac5e-3e3889d7224d").expect("ref id");
+        let mut transport = fixtures::memory_transport();
+        transport.insert(&refset_url(&ref_id), &br#"{ "data": { "Carousel": [] } }"#[..]);
+        let dump = Dump::load(&mut grabber(Arc::new(transport)), &Languages::default(), false).await;
+
+        let row = dump.rows.iter().find(|row| row.ref_id == Some(ref_id)).expect("row");
+        assert!(row.tiles.is_empty());
+        assert!(row.skipped[0].ends_with("is of unknown kind Carousel"), "{:?}", row.skipped);
+        assert!(dump.to_text().contains("    skipped: set "));
+
+        let mut transport = fixtures::memory_transport();
+        transport.insert(crate::menu::menu::HOME_URL, &br#"{ "data": { "Carousel": [] } }"#[..]);
+        let dump = Dump::load(&mut grabber(Arc::new(transport)), &Languages::default(), false).await;
+        assert!(dump.rows.is_empty());
+        assert_eq!(dump.failure.as_deref(), Some("unsupported home page kind Carousel"));
+    }
+
+    // Only asks for later pages when paging is on.
+    #[tokio::test]
+    async fn loads_later_pages() {
+        let ref_id = uuid::Uuid::parse_str("bd1bfb9a-bbf7-43a0-ac5e-3e3889d7224d").expect("ref id");
+        let row = |dump: &Dump| dump.rows.iter().find(|row| row.ref_id == Some(ref_id)).cloned().expect("row");
+
+        let transport = Arc::new(PagedTransport::new(fixtures::memory_transport(), &ref_id, 8, 3));
+        let mut paged = grabber(transport.clone());
+        let dump = Dump::load(&mut paged, &Languages::default(), false).await;
+        assert_eq!(row(&dump).tiles.len(), 3);
+        assert_eq!(row(&dump).skipped, vec!["5 more items on later pages, not loaded with paging off"]);
+        assert!(!transport.requested().iter().any(|url| url.contains("offset=")));
+
+        // Pages are loaded up to what the menu would have before anything is focused.
+        let transport = PagedTransport::new(fixtures::memory_transport(), &ref_id, 30, 5);
+        let dump = Dump::load(&mut grabber(Arc::new(transport)), &Languages::default(), true).await;
+        assert_eq!(row(&dump).tiles.len(), 15);
+        assert_eq!(
+            row(&dump).skipped,
+            vec!["15 more items on later pages, loaded as focus nears the end"]
+        );
+    }
+}
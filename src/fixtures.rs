@@ -0,0 +1,196 @@
+// Helpers for tests to load the sample api responses in `fixtures/` instead of going out to the network.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use reqwest::Url;
+use serde_json::Value;
+use uuid::Uuid;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::{
+    grabber::{
+        strip_query, GrabResult, GrabberConfig, HttpGrabber, MemoryTransport, RetryPolicy, Transport, TransportRequest,
+        TransportResponse,
+    },
+    home::{Home, RefSet},
+    menu::{container::refset_url, menu::HOME_URL},
+};
+
+pub fn fixture_path(path: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures")
+        .join(path)
+}
+
+pub fn fixture_bytes(path: &str) -> Bytes {
+    let path = fixture_path(path);
+    Bytes::from(std::fs::read(&path).unwrap_or_else(|err| panic!("read {:?}: {:?}", path, err)))
+}
+
+pub fn home() -> Home {
+    serde_json::from_slice(&fixture_bytes("home.json")).expect("working home deserialization")
+}
+
+pub fn refset_paths() -> Vec<String> {
+    let mut paths = std::fs::read_dir(fixture_path("sets"))
+        .expect("fixture sets")
+        .map(|entry| {
+            format!(
+                "sets/{}",
+                entry.expect("set entry").file_name().to_string_lossy()
+            )
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+pub fn refsets() -> Vec<RefSet> {
+    refset_paths()
+        .iter()
+        .map(|path| {
+            serde_json::from_slice(&fixture_bytes(path)).expect("working refset deserialization")
+        })
+        .collect()
+}
+
+// Tiny png so tiles have something real to decode.
+pub fn png_bytes() -> Bytes {
+    let mut bytes = Vec::new();
+    image::DynamicImage::new_rgba8(2, 2)
+        .write_to(&mut bytes, image::ImageOutputFormat::Png)
+        .expect("encode png");
+    Bytes::from(bytes)
+}
+
+// Every image url referenced by the home page and sets.
+pub fn image_urls() -> Vec<String> {
+    let home = home();
+    let collection = home.data.collection().expect("fixture collection");
+    let containers = collection.containers.iter().map(|container| container.set.clone());
+    let refsets = refsets();
+    let sets = containers.chain(refsets.iter().filter_map(|refset| Some(refset.data.set()?.into_owned())));
+
+    let mut urls = Vec::new();
+    for set in sets {
+        for item in set.items.iter().flatten() {
+            for image in item.image.tile.values() {
+                urls.push(image.details().url.clone());
+            }
+        }
+    }
+    urls.sort();
+    urls.dedup();
+    urls
+}
+
+// Serves the home page, sets and a png for every image like the real api would.
+pub fn memory_transport() -> MemoryTransport {
+    let mut transport = MemoryTransport::new();
+    transport.insert(HOME_URL, fixture_bytes("home.json"));
+    for path in refset_paths() {
+        let ref_id = fixture_path(&path)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| Uuid::parse_str(stem).ok())
+            .expect("refset fixtures are named by ref id");
+        transport.insert(&refset_url(&ref_id), fixture_bytes(&path));
+    }
+
+    let png = png_bytes();
+    // Tiles ask for whatever image width they need, so serve every size.
+    for url in image_urls() {
+        transport.insert(strip_query(&url), png.clone());
+    }
+
+    transport
+}
+
+// Grabber that only ever goes to `transport`, without a disk cache or retries to slow tests down.
+pub fn offline_grabber<T: Transport + 'static>(transport: Arc<T>) -> HttpGrabber {
+    HttpGrabber::with_config(GrabberConfig {
+        transport,
+        disk_cache: None,
+        retry: RetryPolicy::never(),
+        ..Default::default()
+    })
+}
+
+// Serves the fixture set `ref_id` a page at a time like an api that pages would, going by `offset` and
+// `page_size` in the query. There are `hits` items, past the fixture's own they are copies of them with
+// new content ids so every position in the set is a different title.
+#[derive(Debug)]
+pub struct PagedTransport {
+    inner: MemoryTransport,
+    set_url: String,
+    set: Value,
+    hits: u32,
+    page_size: u32,
+}
+
+impl PagedTransport {
+    // Anything other than the set is left to `inner`.
+    pub fn new(inner: MemoryTransport, ref_id: &Uuid, hits: u32, page_size: u32) -> Self {
+        let path = format!("sets/{}.json", ref_id.to_hyphenated());
+        let set = serde_json::from_slice(&fixture_bytes(&path)).expect("set json");
+        Self {
+            inner,
+            set_url: refset_url(ref_id),
+            set,
+            hits,
+            page_size,
+        }
+    }
+
+    // Content id of the item at `position` in the set.
+    pub fn content_id(&self, position: u32) -> Uuid {
+        let items = self.set["data"]["CuratedSet"]["items"].as_array().expect("items");
+        match items.get(position as usize) {
+            Some(item) => Uuid::parse_str(item["contentId"].as_str().expect("content id")).expect("content id"),
+            None => Uuid::from_u128(0x9000 + position as u128),
+        }
+    }
+
+    pub fn page(&self, offset: u32, page_size: u32) -> Bytes {
+        let mut page = self.set.clone();
+        let items = page["data"]["CuratedSet"]["items"].as_array().expect("items").clone();
+        let end = offset.saturating_add(page_size).min(self.hits);
+        page["data"]["CuratedSet"]["items"] = (offset..end)
+            .map(|position| {
+                let mut item = items[position as usize % items.len()].clone();
+                item["contentId"] = Value::String(self.content_id(position).to_string());
+                item
+            })
+            .collect();
+        page["data"]["CuratedSet"]["meta"] =
+            serde_json::json!({ "hits": self.hits, "offset": offset, "page_size": page_size });
+        Bytes::from(serde_json::to_vec(&page).expect("page json"))
+    }
+
+    pub fn requested(&self) -> Vec<String> {
+        self.inner.requested()
+    }
+}
+
+#[async_trait]
+impl Transport for PagedTransport {
+    async fn fetch(&self, request: TransportRequest) -> GrabResult<TransportResponse> {
+        // Always goes through `inner` so it shows up in `requested`.
+        let response = self.inner.fetch(request.clone()).await?;
+        if strip_query(&request.url) != self.set_url {
+            return Ok(response);
+        }
+
+        let url = Url::parse(&request.url).expect("set url");
+        let query = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .and_then(|(_, value)| value.parse::<u32>().ok())
+        };
+        let offset = query("offset").unwrap_or(0);
+        let page_size = query("page_size").unwrap_or(self.page_size);
+        Ok(TransportResponse::ok(self.page(offset, page_size)).with_header("content-type", "application/json"))
+    }
+}
@@ -0,0 +1,59 @@
+// The bits of `Cache-Control` that matter for deciding how long a cached response stays usable.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    pub max_age: Option<u64>,
+    // Can be cached, but has to be revalidated before every use.
+    pub no_cache: bool,
+    pub no_store: bool,
+}
+
+impl CacheControl {
+    pub fn parse(value: &str) -> Self {
+        let mut cache_control = CacheControl::default();
+
+        for directive in value.split(',') {
+            let mut parts = directive.splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+            let argument = parts.next().map(|argument| argument.trim().trim_matches('"'));
+
+            match name.as_str() {
+                "max-age" => cache_control.max_age = argument.and_then(|argument| argument.parse().ok()),
+                "no-cache" => cache_control.no_cache = true,
+                "no-store" => cache_control.no_store = true,
+                _ => {}
+            }
+        }
+
+        cache_control
+    }
+
+    // Seconds the response can be used without revalidating, `None` when the server didn't say.
+    pub fn freshness(&self) -> Option<u64> {
+        if self.no_cache {
+            Some(0)
+        } else {
+            self.max_age
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CacheControl;
+
+    #[test]
+    fn parses_directives() {
+        let parsed = CacheControl::parse("public, max-age=300, must-revalidate");
+        assert_eq!(parsed.max_age, Some(300));
+        assert_eq!(parsed.freshness(), Some(300));
+        assert!(!parsed.no_store);
+
+        let parsed = CacheControl::parse("No-Cache, max-age=\"60\"");
+        assert_eq!(parsed.max_age, Some(60));
+        assert_eq!(parsed.freshness(), Some(0));
+
+        assert!(CacheControl::parse("no-store").no_store);
+        assert_eq!(CacheControl::parse("max-age=soon").freshness(), None);
+        assert_eq!(CacheControl::parse("").freshness(), None);
+    }
+}
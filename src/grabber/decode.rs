@@ -0,0 +1,265 @@
+use bytes::Bytes;
+use image::RgbaImage;
+
+use std::sync::{Arc, Weak};
+
+use crate::home::{Home, RefSet};
+
+use super::{GrabError, GrabResult, Safeguards};
+
+// What the worker should turn a response into before handing it back, so the event loop never has
+// to decode images or parse json itself.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Decode {
+    Raw,
+    Image,
+    // Middle of the image in another shape, the aspect ratio in thousandths so it can be hashed.
+    CroppedImage(u32),
+    Home,
+    RefSet,
+}
+
+// Cheap to clone, everyone asking for the same url shares the one decoded response.
+#[derive(Debug, Clone)]
+pub enum Payload {
+    Raw(Bytes),
+    // Already converted to RGBA so it only needs uploading.
+    Image(Arc<RgbaImage>),
+    Home(Arc<Home>),
+    RefSet(Arc<RefSet>),
+}
+
+// Payload that doesn't keep anything alive, for handing out again while someone still has it.
+//
+// `Bytes` can't be held weakly, so raw responses are only ever shared while they are in flight.
+#[derive(Debug, Clone)]
+pub enum WeakPayload {
+    Image(Weak<RgbaImage>),
+    Home(Weak<Home>),
+    RefSet(Weak<RefSet>),
+}
+
+impl WeakPayload {
+    pub fn upgrade(&self) -> Option<Payload> {
+        match self {
+            WeakPayload::Image(image) => image.upgrade().map(Payload::Image),
+            WeakPayload::Home(home) => home.upgrade().map(Payload::Home),
+            WeakPayload::RefSet(refset) => refset.upgrade().map(Payload::RefSet),
+        }
+    }
+
+    pub fn is_alive(&self) -> bool {
+        match self {
+            WeakPayload::Image(image) => image.strong_count() > 0,
+            WeakPayload::Home(home) => home.strong_count() > 0,
+            WeakPayload::RefSet(refset) => refset.strong_count() > 0,
+        }
+    }
+}
+
+impl Decode {
+    // Image cropped down to `aspect_ratio`, close enough ratios end up the same.
+    pub fn cropped_image(aspect_ratio: f32) -> Decode {
+        Decode::CroppedImage((aspect_ratio * 1000.0).round() as u32)
+    }
+
+    pub fn is_image(self) -> bool {
+        matches!(self, Decode::Image | Decode::CroppedImage(_))
+    }
+
+    // Slow for big images, so run this somewhere it can block.
+    pub fn decode(self, bytes: Bytes) -> GrabResult<Payload> {
+        match self {
+            Decode::Raw => Ok(Payload::Raw(bytes)),
+            Decode::Image => {
+                let image = image::load_from_memory(&bytes).map_err(|err| GrabError::Decode(err.to_string()))?;
+                Ok(Payload::Image(Arc::new(image.into_rgba8())))
+            }
+            Decode::CroppedImage(aspect_ratio) => {
+                let image = image::load_from_memory(&bytes).map_err(|err| GrabError::Decode(err.to_string()))?;
+                let cropped = crop_to(&image.into_rgba8(), aspect_ratio as f32 / 1000.0);
+                Ok(Payload::Image(Arc::new(cropped)))
+            }
+            Decode::Home => {
+                let home = serde_json::from_slice::<Home>(&bytes).map_err(|err| GrabError::Decode(err.to_string()))?;
+                Ok(Payload::Home(Arc::new(home)))
+            }
+            Decode::RefSet => {
+                let refset =
+                    serde_json::from_slice::<RefSet>(&bytes).map_err(|err| GrabError::Decode(err.to_string()))?;
+                Ok(Payload::RefSet(Arc::new(refset)))
+            }
+        }
+    }
+}
+
+impl Decode {
+    // Same as `decode`, but refusing anything `safeguards` doesn't like the look of before spending
+    // any time or memory on it.
+    pub fn decode_checked(
+        self,
+        bytes: Bytes,
+        content_type: Option<&str>,
+        safeguards: &Safeguards,
+    ) -> GrabResult<Payload> {
+        safeguards.check_content_type(self, content_type)?;
+        if self.is_image() {
+            safeguards.check_image(&bytes)?;
+        }
+        self.decode(bytes)
+    }
+}
+
+// Middle of `image` in the shape of `aspect_ratio`, cutting off the sides or the top and bottom.
+pub fn crop_to(image: &RgbaImage, aspect_ratio: f32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let (crop_width, crop_height) = if width as f32 / height as f32 > aspect_ratio {
+        (((height as f32 * aspect_ratio).round() as u32).clamp(1, width), height)
+    } else {
+        (width, ((width as f32 / aspect_ratio).round() as u32).clamp(1, height))
+    };
+
+    image::imageops::crop_imm(image, (width - crop_width) / 2, (height - crop_height) / 2, crop_width, crop_height)
+        .to_image()
+}
+
+// Asking for the wrong kind of payload back is a bug on our side, not something about the response.
+impl Payload {
+    pub fn decoded_as(&self) -> Decode {
+        match self {
+            Payload::Raw(_) => Decode::Raw,
+            Payload::Image(_) => Decode::Image,
+            Payload::Home(_) => Decode::Home,
+            Payload::RefSet(_) => Decode::RefSet,
+        }
+    }
+
+    pub fn downgrade(&self) -> Option<WeakPayload> {
+        match self {
+            Payload::Raw(_) => None,
+            Payload::Image(image) => Some(WeakPayload::Image(Arc::downgrade(image))),
+            Payload::Home(home) => Some(WeakPayload::Home(Arc::downgrade(home))),
+            Payload::RefSet(refset) => Some(WeakPayload::RefSet(Arc::downgrade(refset))),
+        }
+    }
+
+    fn mismatch(&self, wanted: Decode) -> GrabError {
+        GrabError::Worker(format!("wanted {:?} payload, got {:?}", wanted, self.decoded_as()))
+    }
+
+    pub fn into_raw(self) -> GrabResult<Bytes> {
+        match self {
+            Payload::Raw(bytes) => Ok(bytes),
+            other => Err(other.mismatch(Decode::Raw)),
+        }
+    }
+
+    pub fn into_image(self) -> GrabResult<Arc<RgbaImage>> {
+        match self {
+            Payload::Image(image) => Ok(image),
+            other => Err(other.mismatch(Decode::Image)),
+        }
+    }
+
+    pub fn into_home(self) -> GrabResult<Arc<Home>> {
+        match self {
+            Payload::Home(home) => Ok(home),
+            other => Err(other.mismatch(Decode::Home)),
+        }
+    }
+
+    pub fn into_refset(self) -> GrabResult<Arc<RefSet>> {
+        match self {
+            Payload::RefSet(refset) => Ok(refset),
+            other => Err(other.mismatch(Decode::RefSet)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{crop_to, Decode};
+    use crate::fixtures;
+    use crate::grabber::{GrabError, Safeguards};
+    use bytes::Bytes;
+    use std::sync::Arc;
+
+    #[test]
+    fn decodes_on_request() {
+        let image = Decode::Image
+            .decode(fixtures::png_bytes())
+            .expect("decode png")
+            .into_image()
+            .expect("image payload");
+        assert_eq!(image.dimensions(), (2, 2));
+
+        let home = Decode::Home
+            .decode(fixtures::fixture_bytes("home.json"))
+            .expect("parse home")
+            .into_home()
+            .expect("home payload");
+        assert_eq!(home.data.collection().expect("collection").containers.len(), 3);
+
+        let raw = Bytes::from_static(b"not an image");
+        assert!(matches!(Decode::Image.decode(raw.clone()), Err(GrabError::Decode(_))));
+        let payload = Decode::Raw.decode(raw.clone()).expect("raw");
+        assert!(payload.clone().into_image().is_err());
+        assert!(payload.downgrade().is_none());
+        assert_eq!(payload.into_raw(), Ok(raw));
+    }
+
+    #[test]
+    fn crops_images() {
+        let square = image::RgbaImage::new(100, 100);
+        assert_eq!(crop_to(&square, 1.78).dimensions(), (100, 56));
+        assert_eq!(crop_to(&square, 0.71).dimensions(), (71, 100));
+
+        let cropped = Decode::cropped_image(1.78)
+            .decode(fixtures::png_bytes())
+            .expect("decode png")
+            .into_image()
+            .expect("image payload");
+        assert_eq!(cropped.dimensions(), (2, 1));
+        assert_eq!(Decode::cropped_image(1.7801), Decode::cropped_image(1.78));
+        assert!(Decode::cropped_image(1.78).is_image());
+    }
+
+    #[test]
+    fn decodes_safely() {
+        let strict = Safeguards {
+            max_image_dimension: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            Decode::Image
+                .decode_checked(fixtures::png_bytes(), Some("image/png"), &strict)
+                .map(|_| ()),
+            Err(GrabError::ImageTooLarge { width: 2, height: 2 })
+        );
+
+        let html = Bytes::from_static(b"<html>oops</html>");
+        assert_eq!(
+            Decode::Home
+                .decode_checked(html, Some("text/html"), &Safeguards::default())
+                .map(|_| ()),
+            Err(GrabError::UnexpectedContentType("text/html".to_owned()))
+        );
+
+        assert!(Decode::Image
+            .decode_checked(fixtures::png_bytes(), None, &Safeguards::default())
+            .is_ok());
+    }
+
+    #[test]
+    fn weak_payloads() {
+        let payload = Decode::Image.decode(fixtures::png_bytes()).expect("decode png");
+        let weak = payload.downgrade().expect("images can be held weakly");
+
+        let shared = weak.upgrade().expect("still held").into_image().expect("image payload");
+        assert!(Arc::ptr_eq(&shared, &payload.into_image().expect("image payload")));
+
+        drop(shared);
+        assert!(!weak.is_alive());
+        assert!(weak.upgrade().is_none());
+    }
+}
@@ -0,0 +1,509 @@
+use anyhow::Result;
+use bytes::Bytes;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::TransportRequest;
+
+const INDEX_FILE: &str = "index.json";
+// The index is rewritten after this many new or revalidated entries, or once the oldest unwritten one
+// is this old, rather than after every single image.
+const FLUSH_EVERY: u32 = 32;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+// How long a response the server didn't say anything about is used before being fetched again.
+const HEURISTIC_MAX_AGE: u64 = 60 * 60;
+
+#[derive(Debug, Clone)]
+pub struct DiskCacheConfig {
+    pub directory: PathBuf,
+    // Total size of cached bodies before least recently used entries get evicted.
+    pub max_bytes: u64,
+}
+
+impl Default for DiskCacheConfig {
+    fn default() -> Self {
+        let directory = dirs::cache_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("dss-project");
+
+        Self {
+            directory,
+            max_bytes: 512 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CacheMetadata {
+    pub url: String,
+    pub file: String,
+    pub size: u64,
+    // Seconds since the unix epoch.
+    pub fetched_at: u64,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<String>,
+    // Seconds after `fetched_at` the body can be used without revalidating.
+    #[serde(default)]
+    pub max_age: Option<u64>,
+    // Monotonic counter, higher means more recently used.
+    pub last_used: u64,
+}
+
+impl CacheMetadata {
+    // Whether the cached body can be used without checking with the server first.
+    pub fn is_fresh(&self, now: u64) -> bool {
+        match self.max_age {
+            Some(max_age) => now < self.fetched_at.saturating_add(max_age),
+            // The server never said how long it lasts, so revalidate whenever we can and otherwise
+            // only hold on to it for a while.
+            None if self.has_validators() => false,
+            None => now < self.fetched_at.saturating_add(HEURISTIC_MAX_AGE),
+        }
+    }
+
+    pub fn has_validators(&self) -> bool {
+        self.etag.is_some() || self.last_modified.is_some()
+    }
+
+    // Turn `request` into one the server can answer with `304 Not Modified` if this is still current.
+    pub fn conditional(&self, mut request: TransportRequest) -> TransportRequest {
+        if let Some(etag) = &self.etag {
+            request = request.with_header("if-none-match", etag);
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.with_header("if-modified-since", last_modified);
+        }
+        request
+    }
+}
+
+// Response headers that get stored alongside a cached body.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CacheHeaders {
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub max_age: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub metadata: CacheMetadata,
+    pub body: Bytes,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct CacheIndex {
+    entries: Vec<CacheMetadata>,
+}
+
+// Responses stored on disk keyed by url, with an index file alongside them so it survives restarts.
+//
+// Everything here is blocking file io, so it should be used from a blocking task.
+#[derive(Debug)]
+pub struct DiskCache {
+    config: DiskCacheConfig,
+    entries: HashMap<String, CacheMetadata>,
+    total_bytes: u64,
+    use_counter: u64,
+    dirty: bool,
+    // Entries added or revalidated since the index was last written, and when the first of them was.
+    unflushed: u32,
+    unflushed_since: Option<Instant>,
+}
+
+impl DiskCache {
+    pub fn open(config: DiskCacheConfig) -> Result<Self> {
+        fs::create_dir_all(&config.directory)?;
+
+        let index = match fs::read(config.directory.join(INDEX_FILE)) {
+            Ok(bytes) => serde_json::from_slice::<CacheIndex>(&bytes).unwrap_or_else(|err| {
+                eprintln!("disk cache index is corrupt, starting fresh: {:?}", err);
+                CacheIndex::default()
+            }),
+            Err(err) if err.kind() == ErrorKind::NotFound => CacheIndex::default(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut cache = Self {
+            config,
+            entries: HashMap::new(),
+            total_bytes: 0,
+            use_counter: 0,
+            dirty: false,
+            unflushed: 0,
+            unflushed_since: None,
+        };
+
+        for metadata in index.entries {
+            // Files might have been cleaned up behind our back.
+            if !cache.config.directory.join(&metadata.file).is_file() {
+                cache.dirty = true;
+                continue;
+            }
+
+            cache.total_bytes += metadata.size;
+            cache.use_counter = cache.use_counter.max(metadata.last_used);
+            cache.entries.insert(metadata.url.clone(), metadata);
+        }
+
+        cache.evict()?;
+        cache.flush()?;
+        Ok(cache)
+    }
+
+    pub fn config(&self) -> &DiskCacheConfig {
+        &self.config
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    pub fn contains(&self, url: &str) -> bool {
+        self.entries.contains_key(url)
+    }
+
+    pub fn metadata(&self, url: &str) -> Option<&CacheMetadata> {
+        self.entries.get(url)
+    }
+
+    pub fn get(&mut self, url: &str) -> Option<CacheEntry> {
+        let path = self.config.directory.join(&self.entries.get(url)?.file);
+
+        match fs::read(&path) {
+            Ok(body) => {
+                self.use_counter += 1;
+                let metadata = self.entries.get_mut(url)?;
+                metadata.last_used = self.use_counter;
+                self.dirty = true;
+
+                Some(CacheEntry {
+                    metadata: metadata.clone(),
+                    body: Bytes::from(body),
+                })
+            }
+            Err(err) => {
+                eprintln!("failed to read cached {}: {:?}", url, err);
+                self.remove(url);
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, url: &str, body: &Bytes, headers: CacheHeaders) -> Result<()> {
+        let file = self.file_for(url);
+
+        // Write to a temporary file first so a crash never leaves a half written body in the index.
+        let temporary = self.config.directory.join(format!("{}.tmp", file));
+        fs::write(&temporary, body)?;
+        fs::rename(&temporary, self.config.directory.join(&file))?;
+
+        if let Some(previous) = self.entries.remove(url) {
+            self.total_bytes -= previous.size;
+        }
+
+        self.use_counter += 1;
+        let metadata = CacheMetadata {
+            url: url.to_owned(),
+            file,
+            size: body.len() as u64,
+            fetched_at: unix_now(),
+            content_type: headers.content_type,
+            etag: headers.etag,
+            last_modified: headers.last_modified,
+            max_age: headers.max_age,
+            last_used: self.use_counter,
+        };
+
+        self.total_bytes += metadata.size;
+        self.entries.insert(url.to_owned(), metadata);
+        self.dirty = true;
+
+        self.evict()?;
+        self.flush_batched()
+    }
+
+    // Named by the url's hash, unless some other url with the same hash already has that file.
+    fn file_for(&self, url: &str) -> String {
+        let hash = url_hash(url);
+        let mut file = format!("{:016x}.bin", hash);
+        let mut collisions = 0;
+        while self.entries.values().any(|other| other.url != url && other.file == file) {
+            collisions += 1;
+            file = format!("{:016x}-{}.bin", hash, collisions);
+        }
+        file
+    }
+
+    // Server said the cached body is still current, so it is fresh again from now.
+    //
+    // `max_age` only replaces the stored one when the revalidation response had one of its own.
+    pub fn revalidate(&mut self, url: &str, max_age: Option<u64>) -> Result<()> {
+        if let Some(metadata) = self.entries.get_mut(url) {
+            metadata.fetched_at = unix_now();
+            if max_age.is_some() {
+                metadata.max_age = max_age;
+            }
+            self.dirty = true;
+        }
+
+        self.flush_batched()
+    }
+
+    pub fn remove(&mut self, url: &str) {
+        if let Some(metadata) = self.entries.remove(url) {
+            self.total_bytes -= metadata.size;
+            self.dirty = true;
+
+            // Url hashes could collide, so only delete the file if nothing else points at it.
+            if !self.entries.values().any(|other| other.file == metadata.file) {
+                let _ = fs::remove_file(self.config.directory.join(&metadata.file));
+            }
+        }
+    }
+
+    // Throw out least recently used entries until we are back under the size limit.
+    fn evict(&mut self) -> Result<()> {
+        if self.total_bytes <= self.config.max_bytes {
+            return Ok(());
+        }
+
+        let mut by_use = self
+            .entries
+            .values()
+            .map(|metadata| (metadata.last_used, metadata.url.clone()))
+            .collect::<Vec<_>>();
+        by_use.sort();
+
+        for (_, url) in by_use {
+            if self.total_bytes <= self.config.max_bytes {
+                break;
+            }
+
+            self.remove(&url);
+        }
+
+        Ok(())
+    }
+
+    // Only writes the index once enough has changed or it has been a while, a crash in between just
+    // loses track of the newest entries and they get fetched again.
+    fn flush_batched(&mut self) -> Result<()> {
+        self.unflushed += 1;
+        let since = *self.unflushed_since.get_or_insert_with(Instant::now);
+        if self.unflushed >= FLUSH_EVERY || since.elapsed() >= FLUSH_INTERVAL {
+            self.flush()
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.unflushed = 0;
+        self.unflushed_since = None;
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let index = CacheIndex {
+            entries: self.entries.values().cloned().collect(),
+        };
+
+        let temporary = self.config.directory.join(format!("{}.tmp", INDEX_FILE));
+        fs::write(&temporary, serde_json::to_vec(&index)?)?;
+        fs::rename(&temporary, self.config.directory.join(INDEX_FILE))?;
+
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+impl Drop for DiskCache {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            eprintln!("failed to write disk cache index: {:?}", err);
+        }
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+// FNV-1a, std's hasher isn't guaranteed to be stable between releases and these names live on disk.
+pub fn url_hash(url: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in url.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod test {
+    use super::{url_hash, CacheHeaders, CacheMetadata, DiskCache, DiskCacheConfig, FLUSH_EVERY, HEURISTIC_MAX_AGE, INDEX_FILE};
+    use bytes::Bytes;
+
+    fn test_config(name: &str, max_bytes: u64) -> DiskCacheConfig {
+        let directory = std::env::temp_dir().join(format!("dss-disk-cache-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        DiskCacheConfig {
+            directory,
+            max_bytes,
+        }
+    }
+
+    #[test]
+    fn persists_between_opens() {
+        let config = test_config("persist", 1024);
+        let body = Bytes::from_static(b"hello");
+
+        {
+            let mut cache = DiskCache::open(config.clone()).expect("open cache");
+            cache
+                .insert(
+                    "http://a/",
+                    &body,
+                    CacheHeaders {
+                        content_type: Some("text/plain".to_owned()),
+                        etag: Some("\"abc\"".to_owned()),
+                        last_modified: None,
+                        max_age: Some(60),
+                    },
+                )
+                .expect("insert");
+        }
+
+        let mut cache = DiskCache::open(config.clone()).expect("reopen cache");
+        let entry = cache.get("http://a/").expect("cached entry");
+        assert_eq!(entry.body, body);
+        assert_eq!(entry.metadata.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(entry.metadata.etag.as_deref(), Some("\"abc\""));
+        assert_eq!(entry.metadata.max_age, Some(60));
+
+        let _ = std::fs::remove_dir_all(&config.directory);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let config = test_config("evict", 10);
+        let mut cache = DiskCache::open(config.clone()).expect("open cache");
+
+        cache.insert("a", &Bytes::from_static(b"1234"), CacheHeaders::default()).expect("insert a");
+        cache.insert("b", &Bytes::from_static(b"1234"), CacheHeaders::default()).expect("insert b");
+        // Touch a so b becomes the oldest.
+        assert!(cache.get("a").is_some());
+        cache.insert("c", &Bytes::from_static(b"1234"), CacheHeaders::default()).expect("insert c");
+
+        assert!(cache.contains("a"));
+        assert!(!cache.contains("b"));
+        assert!(cache.contains("c"));
+        assert_eq!(cache.total_bytes(), 8);
+
+        let _ = std::fs::remove_dir_all(&config.directory);
+    }
+
+    #[test]
+    fn batches_index_writes() {
+        let config = test_config("batch", 1024);
+        let mut cache = DiskCache::open(config.clone()).expect("open cache");
+        // Nothing is written at all until the first flush.
+        let index = || std::fs::read_to_string(config.directory.join(INDEX_FILE)).unwrap_or_default();
+
+        cache.insert("first", &Bytes::from_static(b"1"), CacheHeaders::default()).expect("insert");
+        assert!(!index().contains("first"));
+
+        for n in 1..FLUSH_EVERY {
+            let url = format!("url-{}", n);
+            cache.insert(&url, &Bytes::from_static(b"1"), CacheHeaders::default()).expect("insert");
+        }
+        assert!(index().contains("first"));
+
+        cache.insert("last", &Bytes::from_static(b"1"), CacheHeaders::default()).expect("insert");
+        drop(cache);
+        assert!(index().contains("last"));
+
+        let _ = std::fs::remove_dir_all(&config.directory);
+    }
+
+    #[test]
+    fn keeps_colliding_urls_apart() {
+        let config = test_config("collide", 1024);
+        let mut cache = DiskCache::open(config.clone()).expect("open cache");
+
+        // Stand in for another url that happens to hash the same as `a`.
+        cache.insert("other", &Bytes::from_static(b"other body"), CacheHeaders::default()).expect("insert other");
+        let mut other = cache.entries.remove("other").expect("other");
+        let file = format!("{:016x}.bin", url_hash("a"));
+        std::fs::rename(config.directory.join(&other.file), config.directory.join(&file)).expect("rename");
+        other.file = file;
+        cache.entries.insert("other".to_owned(), other);
+
+        cache.insert("a", &Bytes::from_static(b"a body"), CacheHeaders::default()).expect("insert a");
+        assert_eq!(cache.get("a").expect("a").body, Bytes::from_static(b"a body"));
+        assert_eq!(cache.get("other").expect("other").body, Bytes::from_static(b"other body"));
+
+        // Replacing `a` keeps the file it already has.
+        let file = cache.metadata("a").expect("a").file.clone();
+        cache.insert("a", &Bytes::from_static(b"a again"), CacheHeaders::default()).expect("insert a");
+        assert_eq!(cache.metadata("a").expect("a").file, file);
+        assert_eq!(cache.get("other").expect("other").body, Bytes::from_static(b"other body"));
+
+        let _ = std::fs::remove_dir_all(&config.directory);
+    }
+
+    #[test]
+    fn freshness() {
+        let metadata = |max_age, etag: Option<&str>| CacheMetadata {
+            url: "a".to_owned(),
+            file: "a.bin".to_owned(),
+            size: 0,
+            fetched_at: 1000,
+            content_type: None,
+            etag: etag.map(|etag| etag.to_owned()),
+            last_modified: None,
+            max_age,
+            last_used: 0,
+        };
+
+        assert!(metadata(Some(60), None).is_fresh(1059));
+        assert!(!metadata(Some(60), None).is_fresh(1060));
+        assert!(!metadata(None, Some("\"v1\"")).is_fresh(1000));
+
+        let request = metadata(None, Some("\"v1\"")).conditional(crate::grabber::TransportRequest::new("a"));
+        assert_eq!(request.header("If-None-Match"), Some("\"v1\""));
+        assert_eq!(request.header("If-Modified-Since"), None);
+    }
+
+    // Without any caching headers at all a response is only used for so long before being fetched again.
+    #[test]
+    fn no_headers_go_stale() {
+        let metadata = CacheMetadata {
+            url: "a".to_owned(),
+            file: "a.bin".to_owned(),
+            size: 0,
+            fetched_at: 1000,
+            content_type: None,
+            etag: None,
+            last_modified: None,
+            max_age: None,
+            last_used: 0,
+        };
+
+        assert!(metadata.is_fresh(1000));
+        assert!(metadata.is_fresh(1000 + HEURISTIC_MAX_AGE - 1));
+        assert!(!metadata.is_fresh(1000 + HEURISTIC_MAX_AGE));
+        assert!(!metadata.is_fresh(u64::MAX));
+    }
+}
@@ -0,0 +1,355 @@
+use flume::{Receiver, Sender};
+use reqwest::Url;
+use tokio::task::{self, JoinHandle};
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+
+use super::{HostPattern, Request, RequestId};
+
+// How badly something on screen wants a request, higher goes first.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    // Anything else we still want loaded eventually.
+    Background,
+    // Just off screen, so it is ready by the time we scroll to it.
+    Prefetch,
+    // On screen right now.
+    Visible,
+    // What the user is looking at.
+    Focused,
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::Visible
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Submit(Request),
+    Prioritise(RequestId, Priority),
+    Cancel(RequestId),
+    // Stop starting requests, the sender hears back once everything is written out.
+    Shutdown(Sender<()>),
+}
+
+// Requests waiting on a free slot, ordered by priority and then by when they were submitted.
+#[derive(Debug, Default)]
+pub struct RequestQueue {
+    ordered: BTreeMap<(Reverse<Priority>, RequestId), Request>,
+    priorities: HashMap<RequestId, Priority>,
+}
+
+impl RequestQueue {
+    pub fn len(&self) -> usize {
+        self.ordered.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ordered.is_empty()
+    }
+
+    pub fn push(&mut self, request: Request) {
+        self.priorities.insert(request.id, request.priority);
+        self.ordered
+            .insert((Reverse(request.priority), request.id), request);
+    }
+
+    // Returns false if the request isn't queued anymore, like when it has already started.
+    pub fn set_priority(&mut self, id: RequestId, priority: Priority) -> bool {
+        match self.remove(id) {
+            Some(mut request) => {
+                request.priority = priority;
+                self.push(request);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove(&mut self, id: RequestId) -> Option<Request> {
+        let priority = self.priorities.remove(&id)?;
+        self.ordered.remove(&(Reverse(priority), id))
+    }
+
+    pub fn pop(&mut self) -> Option<Request> {
+        self.pop_where(|_| true)
+    }
+
+    // Highest priority request that `ready` is happy to start, skipping over any it isn't.
+    pub fn pop_where<F: FnMut(&Request) -> bool>(&mut self, mut ready: F) -> Option<Request> {
+        let key = *self
+            .ordered
+            .iter()
+            .find(|(_, request)| ready(request))
+            .map(|(key, _)| key)?;
+        self.priorities.remove(&key.1);
+        self.ordered.remove(&key)
+    }
+}
+
+// How many requests can be in flight at once.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    pub max_in_flight: usize,
+    // For any one host, so a slow image cdn can't hold up the api.
+    pub max_per_host: usize,
+    // Overrides `max_per_host` for matching hosts, the first match wins.
+    pub host_limits: Vec<(HostPattern, usize)>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_in_flight: 16,
+            max_per_host: 6,
+            host_limits: Vec::new(),
+        }
+    }
+}
+
+impl Limits {
+    pub fn for_host(&self, host: &str) -> usize {
+        self.host_limits
+            .iter()
+            .find(|(pattern, _)| pattern.matches(host))
+            .map_or(self.max_per_host, |(_, limit)| *limit)
+            .max(1)
+    }
+}
+
+// Anything without a host all counts as the same one.
+pub fn host_of(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned))
+        .unwrap_or_default()
+}
+
+// Started requests along with how many there are for each host.
+#[derive(Debug, Default)]
+struct InFlight {
+    handles: HashMap<RequestId, (JoinHandle<()>, String)>,
+    per_host: HashMap<String, usize>,
+}
+
+impl InFlight {
+    fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    fn host_count(&self, host: &str) -> usize {
+        self.per_host.get(host).copied().unwrap_or(0)
+    }
+
+    fn insert(&mut self, id: RequestId, handle: JoinHandle<()>, host: String) {
+        *self.per_host.entry(host.clone()).or_insert(0) += 1;
+        self.handles.insert(id, (handle, host));
+    }
+
+    fn remove(&mut self, id: RequestId) -> Option<JoinHandle<()>> {
+        let (handle, host) = self.handles.remove(&id)?;
+        if let Some(count) = self.per_host.get_mut(&host) {
+            *count -= 1;
+            if *count == 0 {
+                self.per_host.remove(&host);
+            }
+        }
+        Some(handle)
+    }
+}
+
+// Hands queued requests to `start` as slots free up, staying within `limits`. Requests for a host that
+// is already at its limit wait without holding up lower priority requests for other hosts.
+//
+// Runs until every command sender is gone or it's told to shut down, returning whoever asked for that.
+pub async fn dispatch<F, Fut>(commands: Receiver<Command>, limits: Limits, mut start: F) -> Option<Sender<()>>
+where
+    F: FnMut(Request) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let (finished_transmit, finished_receive) = flume::unbounded::<RequestId>();
+    let mut queue = RequestQueue::default();
+    let mut in_flight = InFlight::default();
+    let mut shutdown = None;
+
+    loop {
+        tokio::select! {
+            command = commands.recv_async() => match command {
+                Ok(Command::Submit(request)) => queue.push(request),
+                Ok(Command::Prioritise(id, priority)) => {
+                    // Already started requests just keep going, nothing left to reorder.
+                    queue.set_priority(id, priority);
+                }
+                Ok(Command::Cancel(id)) => {
+                    if queue.remove(id).is_none() {
+                        if let Some(handle) = in_flight.remove(id) {
+                            handle.abort();
+                        }
+                    }
+                }
+                Ok(Command::Shutdown(done)) => {
+                    shutdown = Some(done);
+                    break;
+                }
+                Err(_) => break,
+            },
+            Ok(id) = finished_receive.recv_async() => {
+                in_flight.remove(id);
+            }
+        }
+
+        while in_flight.len() < limits.max_in_flight.max(1) {
+            let request = queue.pop_where(|request| {
+                in_flight.host_count(&request.host) < limits.for_host(&request.host)
+            });
+            let request = match request {
+                Some(request) => request,
+                None => break,
+            };
+
+            let id = request.id;
+            let host = request.host.clone();
+            let future = start(request);
+            let finished_transmit = finished_transmit.clone();
+            let handle = task::spawn(async move {
+                future.await;
+                let _ = finished_transmit.send(id);
+            });
+
+            in_flight.insert(id, handle, host);
+        }
+    }
+
+    for (_, (handle, _)) in in_flight.handles {
+        handle.abort();
+    }
+    shutdown
+}
+
+#[cfg(test)]
+mod test {
+    use super::{dispatch, host_of, Command, Limits, Priority, RequestQueue};
+    use crate::grabber::{Decode, HostPattern, Request, RequestId};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    fn request(id: usize, priority: Priority) -> Request {
+        Request {
+            id: RequestId(id),
+            url: format!("request-{}", id),
+            host: String::new(),
+            priority,
+            decode: Decode::Raw,
+            progress: Default::default(),
+            submitted: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn orders_by_priority_then_submission() {
+        let mut queue = RequestQueue::default();
+        queue.push(request(0, Priority::Background));
+        queue.push(request(1, Priority::Visible));
+        queue.push(request(2, Priority::Focused));
+        queue.push(request(3, Priority::Visible));
+
+        let order = std::iter::from_fn(|| queue.pop())
+            .map(|request| request.id.0)
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec![2, 1, 3, 0]);
+    }
+
+    #[test]
+    fn reprioritise_and_remove() {
+        let mut queue = RequestQueue::default();
+        queue.push(request(0, Priority::Visible));
+        queue.push(request(1, Priority::Background));
+        queue.push(request(2, Priority::Prefetch));
+
+        assert!(queue.set_priority(RequestId(1), Priority::Focused));
+        assert!(queue.remove(RequestId(2)).is_some());
+        assert!(!queue.set_priority(RequestId(2), Priority::Focused));
+        assert_eq!(queue.len(), 2);
+
+        assert_eq!(queue.pop().map(|request| request.id), Some(RequestId(1)));
+        assert_eq!(queue.pop().map(|request| request.id), Some(RequestId(0)));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn host_limits() {
+        let limits = Limits {
+            max_per_host: 4,
+            host_limits: vec![(HostPattern::parse("*.cdn.com"), 2)],
+            ..Default::default()
+        };
+        assert_eq!(limits.for_host("images.cdn.com"), 2);
+        assert_eq!(limits.for_host("api.com"), 4);
+        assert_eq!(host_of("https://api.com/home.json?x=1"), "api.com");
+        assert_eq!(host_of("request-0"), "");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn limits_requests_per_host() {
+        let (command_transmit, command_receive) = flume::unbounded();
+        let (done_transmit, done_receive) = flume::unbounded();
+        let limits = Limits {
+            max_in_flight: 3,
+            max_per_host: 2,
+            host_limits: Vec::new(),
+        };
+
+        // Most requests at once seen for each host, and overall under the "" key.
+        let running = Arc::new(Mutex::new(HashMap::<String, (usize, usize)>::new()));
+        let track = running.clone();
+        tokio::spawn(dispatch(command_receive, limits, move |request| {
+            let running = track.clone();
+            let done_transmit = done_transmit.clone();
+            async move {
+                let host = request.host.clone();
+                for key in [host.clone(), String::new()] {
+                    let mut running = running.lock().unwrap();
+                    let (now, most) = running.entry(key).or_insert((0, 0));
+                    *now += 1;
+                    *most = (*most).max(*now);
+                }
+
+                tokio::time::sleep(Duration::from_millis(10)).await;
+
+                for key in [host, String::new()] {
+                    running.lock().unwrap().get_mut(&key).unwrap().0 -= 1;
+                }
+                let _ = done_transmit.send(request.id);
+            }
+        }));
+
+        // Focused requests for the slow host shouldn't stop the other host getting a slot.
+        for id in 0..8 {
+            let (url, priority) = match id {
+                0..=5 => (format!("https://images.com/{}.jpg", id), Priority::Focused),
+                _ => (format!("https://api.com/{}.json", id), Priority::Background),
+            };
+            let mut request = request(id, priority);
+            request.host = host_of(&url);
+            request.url = url;
+            command_transmit.send(Command::Submit(request)).unwrap();
+        }
+
+        let mut finished = Vec::new();
+        for _ in 0..8 {
+            finished.push(done_receive.recv_async().await.unwrap().0);
+        }
+
+        let running = running.lock().unwrap();
+        assert_eq!(running["images.com"].1, 2);
+        assert_eq!(running["api.com"].1, 1);
+        assert_eq!(running[""].1, 3);
+        // The api requests went out with the first batch instead of waiting on every image.
+        assert!(finished.iter().position(|id| *id == 6).unwrap() < 3);
+    }
+}
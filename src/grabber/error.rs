@@ -0,0 +1,125 @@
+use std::fmt;
+use std::time::Duration;
+
+// Cloneable so the same failure can be handed to the tile and kept around as the url's failed state,
+// anyhow::Error doesn't implement `Clone` so this gets used for anything crossing the grabber boundary.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GrabError {
+    // Couldn't connect, connection dropped, etc.
+    Transport(String),
+    // Server responded, just not with anything we wanted.
+    Status {
+        status: u16,
+        retry_after: Option<Duration>,
+    },
+    Timeout,
+    // Got the whole response but it isn't the image or json it should be.
+    Decode(String),
+    // Body bigger than `Safeguards::max_response_bytes`, given up on as soon as that was clear.
+    ResponseTooLarge {
+        limit: u64,
+    },
+    // Image claims to be wider or taller than allowed, so it never got decoded.
+    ImageTooLarge {
+        width: u32,
+        height: u32,
+    },
+    // Within the dimension limits but too big altogether.
+    TooManyPixels {
+        pixels: u64,
+        limit: u64,
+    },
+    // Content type says the response is something other than what it should be.
+    UnexpectedContentType(String),
+    // Something went wrong on our side of things, like the worker going away.
+    Worker(String),
+}
+
+impl GrabError {
+    pub fn from_reqwest(err: reqwest::Error) -> GrabError {
+        if err.is_timeout() {
+            GrabError::Timeout
+        } else if let Some(status) = err.status() {
+            GrabError::Status {
+                status: status.as_u16(),
+                retry_after: None,
+            }
+        } else {
+            GrabError::Transport(err.to_string())
+        }
+    }
+
+    // Whether trying again later could reasonably give a different result.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            GrabError::Transport(_) | GrabError::Timeout => true,
+            GrabError::Status { status, .. } => {
+                *status == 408 || *status == 429 || (500..600).contains(status)
+            }
+            GrabError::Decode(_)
+            | GrabError::ResponseTooLarge { .. }
+            | GrabError::ImageTooLarge { .. }
+            | GrabError::TooManyPixels { .. }
+            | GrabError::UnexpectedContentType(_)
+            | GrabError::Worker(_) => false,
+        }
+    }
+
+    // Coarse bucket for counting failures, so every distinct message doesn't get its own count.
+    pub fn class(&self) -> &'static str {
+        match self {
+            GrabError::Transport(_) => "transport",
+            GrabError::Status { status, .. } if (400..500).contains(status) => "status_4xx",
+            GrabError::Status { status, .. } if (500..600).contains(status) => "status_5xx",
+            GrabError::Status { .. } => "status_other",
+            GrabError::Timeout => "timeout",
+            GrabError::Decode(_) => "decode",
+            GrabError::ResponseTooLarge { .. } => "response_too_large",
+            GrabError::ImageTooLarge { .. } => "image_too_large",
+            GrabError::TooManyPixels { .. } => "too_many_pixels",
+            GrabError::UnexpectedContentType(_) => "content_type",
+            GrabError::Worker(_) => "worker",
+        }
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            GrabError::Status { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for GrabError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GrabError::Transport(reason) => write!(f, "transport error: {}", reason),
+            GrabError::Status { status, .. } => write!(f, "unexpected status {}", status),
+            GrabError::Timeout => write!(f, "request timed out"),
+            GrabError::Decode(reason) => write!(f, "failed to decode response: {}", reason),
+            GrabError::ResponseTooLarge { limit } => write!(f, "response larger than {} bytes", limit),
+            GrabError::ImageTooLarge { width, height } => write!(f, "image too large ({}x{})", width, height),
+            GrabError::TooManyPixels { pixels, limit } => {
+                write!(f, "image has too many pixels ({} > {})", pixels, limit)
+            }
+            GrabError::UnexpectedContentType(content_type) => {
+                write!(f, "unexpected content type {}", content_type)
+            }
+            GrabError::Worker(reason) => write!(f, "grabber worker error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for GrabError {}
+
+impl From<reqwest::Error> for GrabError {
+    fn from(err: reqwest::Error) -> GrabError {
+        GrabError::from_reqwest(err)
+    }
+}
+
+impl From<tokio::task::JoinError> for GrabError {
+    fn from(err: tokio::task::JoinError) -> GrabError {
+        GrabError::Worker(err.to_string())
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use super::Decode;
+
+// Where the body of a finished request came from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheOutcome {
+    // Fresh on disk, never touched the network.
+    Hit,
+    // Stale on disk, the server said it was still good.
+    Revalidated,
+    // Stale on disk, and used anyway because revalidating it failed.
+    Stale,
+    // Not on disk, or changed since.
+    Miss,
+    // No disk cache at all.
+    Disabled,
+}
+
+// Everything measured about one request, sent back from the worker with its response.
+#[derive(Debug, Clone)]
+pub struct RequestMetrics {
+    pub url: String,
+    pub kind: &'static str,
+    // Waiting in the priority queue for a free slot.
+    pub queue_time: Duration,
+    // From leaving the queue until the first response came back, `None` if nothing ever did.
+    pub time_to_first_byte: Option<Duration>,
+    // From leaving the queue until decoded.
+    pub total_time: Duration,
+    // Size of the body, wherever it came from.
+    pub bytes: u64,
+    // Body bytes actually read off the network for the last attempt.
+    pub network_bytes: u64,
+    // `None` when the request failed before there was a body.
+    pub cache: Option<CacheOutcome>,
+    pub error: Option<&'static str>,
+}
+
+impl RequestMetrics {
+    pub fn kind_of(decode: Decode) -> &'static str {
+        match decode {
+            Decode::Raw => "raw",
+            Decode::Image | Decode::CroppedImage(_) => "image",
+            Decode::Home => "home",
+            Decode::RefSet => "refset",
+        }
+    }
+}
+
+// Roughly what happened to a bunch of requests, in milliseconds.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TimingStats {
+    pub count: usize,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+}
+
+impl TimingStats {
+    fn from_samples(samples: &[Duration]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+
+        let mut samples = samples
+            .iter()
+            .map(|sample| sample.as_secs_f64() * 1000.0)
+            .collect::<Vec<_>>();
+        samples.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        let percentile = |percent: f64| {
+            let index = ((samples.len() - 1) as f64 * percent).round() as usize;
+            samples[index]
+        };
+
+        Self {
+            count: samples.len(),
+            mean_ms: samples.iter().sum::<f64>() / samples.len() as f64,
+            p50_ms: percentile(0.5),
+            p95_ms: percentile(0.95),
+            max_ms: samples[samples.len() - 1],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub revalidated: u64,
+    pub stale: u64,
+    pub misses: u64,
+    pub disabled: u64,
+    // Share of cacheable requests that didn't need a full download, 0 when there weren't any.
+    pub hit_rate: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RequestStats {
+    pub requests: u64,
+    pub failed: u64,
+    pub bytes: u64,
+    pub network_bytes: u64,
+    pub cache: CacheStats,
+    // Failures by `GrabError::class`.
+    pub errors: BTreeMap<String, u64>,
+    pub queue_time: TimingStats,
+    pub time_to_first_byte: TimingStats,
+    pub total_time: TimingStats,
+}
+
+// Snapshot of everything the grabber has done so far.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct GrabberStats {
+    pub overall: RequestStats,
+    // Broken down by what the response was decoded as, like `image` or `home`.
+    pub by_kind: BTreeMap<String, RequestStats>,
+    pub cancelled: u64,
+    // Requests answered by a download that was already happening, or a response someone still had,
+    // so they never count as requests of their own.
+    pub shared: u64,
+}
+
+// Running totals, kept on the grabber side so the worker never has to share anything.
+#[derive(Debug, Default)]
+pub struct MetricsRecorder {
+    overall: Recorded,
+    by_kind: BTreeMap<&'static str, Recorded>,
+    // Most recent request for each url.
+    latest: HashMap<String, RequestMetrics>,
+    cancelled: u64,
+    shared: u64,
+}
+
+#[derive(Debug, Default)]
+struct Recorded {
+    stats: RequestStats,
+    queue_times: Vec<Duration>,
+    first_byte_times: Vec<Duration>,
+    total_times: Vec<Duration>,
+}
+
+impl Recorded {
+    fn record(&mut self, metrics: &RequestMetrics) {
+        let stats = &mut self.stats;
+        stats.requests += 1;
+        stats.bytes += metrics.bytes;
+        stats.network_bytes += metrics.network_bytes;
+
+        match metrics.cache {
+            Some(CacheOutcome::Hit) => stats.cache.hits += 1,
+            Some(CacheOutcome::Revalidated) => stats.cache.revalidated += 1,
+            Some(CacheOutcome::Stale) => stats.cache.stale += 1,
+            Some(CacheOutcome::Miss) => stats.cache.misses += 1,
+            Some(CacheOutcome::Disabled) | None => stats.cache.disabled += 1,
+        }
+
+        if let Some(error) = metrics.error {
+            stats.failed += 1;
+            *stats.errors.entry(error.to_owned()).or_insert(0) += 1;
+        }
+
+        self.queue_times.push(metrics.queue_time);
+        self.first_byte_times.extend(metrics.time_to_first_byte);
+        self.total_times.push(metrics.total_time);
+    }
+
+    fn snapshot(&self) -> RequestStats {
+        let mut stats = self.stats.clone();
+
+        let cache = &mut stats.cache;
+        let cacheable = cache.hits + cache.revalidated + cache.stale + cache.misses;
+        if cacheable > 0 {
+            cache.hit_rate = (cacheable - cache.misses) as f64 / cacheable as f64;
+        }
+
+        stats.queue_time = TimingStats::from_samples(&self.queue_times);
+        stats.time_to_first_byte = TimingStats::from_samples(&self.first_byte_times);
+        stats.total_time = TimingStats::from_samples(&self.total_times);
+        stats
+    }
+}
+
+impl MetricsRecorder {
+    pub fn record(&mut self, metrics: RequestMetrics) {
+        self.overall.record(&metrics);
+        self.by_kind.entry(metrics.kind).or_default().record(&metrics);
+        self.latest.insert(metrics.url.clone(), metrics);
+    }
+
+    pub fn record_cancel(&mut self) {
+        self.cancelled += 1;
+    }
+
+    pub fn record_shared(&mut self) {
+        self.shared += 1;
+    }
+
+    pub fn latest(&self, url: &str) -> Option<&RequestMetrics> {
+        self.latest.get(url)
+    }
+
+    pub fn snapshot(&self) -> GrabberStats {
+        GrabberStats {
+            overall: self.overall.snapshot(),
+            by_kind: self
+                .by_kind
+                .iter()
+                .map(|(kind, recorded)| (kind.to_string(), recorded.snapshot()))
+                .collect(),
+            cancelled: self.cancelled,
+            shared: self.shared,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CacheOutcome, MetricsRecorder, RequestMetrics, TimingStats};
+    use crate::grabber::GrabError;
+    use std::time::Duration;
+
+    fn metrics(url: &str, kind: &'static str, millis: u64, cache: Option<CacheOutcome>) -> RequestMetrics {
+        RequestMetrics {
+            url: url.to_owned(),
+            kind,
+            queue_time: Duration::from_millis(1),
+            time_to_first_byte: cache.map(|_| Duration::from_millis(millis / 2)),
+            total_time: Duration::from_millis(millis),
+            bytes: 100,
+            network_bytes: if cache == Some(CacheOutcome::Hit) { 0 } else { 100 },
+            cache,
+            error: match cache {
+                Some(_) => None,
+                None => Some(GrabError::Status { status: 404, retry_after: None }.class()),
+            },
+        }
+    }
+
+    #[test]
+    fn summarises_requests() {
+        let mut recorder = MetricsRecorder::default();
+        recorder.record(metrics("home", "home", 40, Some(CacheOutcome::Revalidated)));
+        recorder.record(metrics("a", "image", 10, Some(CacheOutcome::Hit)));
+        recorder.record(metrics("b", "image", 20, Some(CacheOutcome::Miss)));
+        recorder.record(metrics("c", "image", 30, None));
+        recorder.record_cancel();
+
+        let stats = recorder.snapshot();
+        assert_eq!(stats.overall.requests, 4);
+        assert_eq!(stats.overall.failed, 1);
+        assert_eq!(stats.overall.errors["status_4xx"], 1);
+        assert_eq!(stats.overall.network_bytes, 300);
+        assert_eq!(stats.overall.cache.hit_rate, 2.0 / 3.0);
+        assert_eq!(stats.cancelled, 1);
+
+        let images = &stats.by_kind["image"];
+        assert_eq!(images.requests, 3);
+        assert_eq!(images.total_time.max_ms, 30.0);
+        assert_eq!(images.time_to_first_byte.count, 2);
+        assert_eq!(recorder.latest("home").map(|home| home.total_time), Some(Duration::from_millis(40)));
+
+        let json = serde_json::to_value(&stats).expect("stats json");
+        assert_eq!(json["by_kind"]["home"]["cache"]["revalidated"], 1);
+    }
+
+    #[test]
+    fn percentiles() {
+        let samples = (1..=100).map(Duration::from_millis).collect::<Vec<_>>();
+        let timing = TimingStats::from_samples(&samples);
+        assert_eq!(timing.count, 100);
+        assert_eq!(timing.mean_ms, 50.5);
+        assert_eq!(timing.p95_ms, 95.0);
+        assert_eq!(timing.max_ms, 100.0);
+        assert_eq!(TimingStats::from_samples(&[]), TimingStats::default());
+    }
+}
@@ -0,0 +1,330 @@
+use async_trait::async_trait;
+use reqwest::Url;
+
+use std::fmt;
+use std::sync::Arc;
+
+use super::{DownloadProgress, GrabResult, Transport, TransportRequest, TransportResponse};
+
+// Something that gets a look at every request on its way out and every response on its way back, for
+// adding auth, rewriting urls for staging and the like.
+pub trait Middleware: fmt::Debug + Send + Sync {
+    fn request(&self, _request: &mut TransportRequest) {}
+
+    // `request` is what was actually sent, after every middleware has had a go at it.
+    fn response(&self, _request: &TransportRequest, _response: &GrabResult<TransportResponse>) {}
+}
+
+// Which hosts a middleware or proxy applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostPattern {
+    Any,
+    Exact(String),
+    // `*.example.com`, matches `example.com` itself as well.
+    Subdomains(String),
+}
+
+impl HostPattern {
+    pub fn parse(pattern: &str) -> Self {
+        let pattern = pattern.trim().to_ascii_lowercase();
+        if pattern.is_empty() || pattern == "*" {
+            HostPattern::Any
+        } else if let Some(domain) = pattern.strip_prefix("*.") {
+            HostPattern::Subdomains(domain.to_owned())
+        } else {
+            HostPattern::Exact(pattern)
+        }
+    }
+
+    pub fn matches(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        match self {
+            HostPattern::Any => true,
+            HostPattern::Exact(exact) => host == *exact,
+            HostPattern::Subdomains(domain) => {
+                host == *domain || host.strip_suffix(domain.as_str()).map_or(false, |rest| rest.ends_with('.'))
+            }
+        }
+    }
+
+    // Anything that isn't a url with a host only matches `Any`.
+    pub fn matches_url(&self, url: &str) -> bool {
+        match Url::parse(url) {
+            Ok(url) => url.host_str().map_or(*self == HostPattern::Any, |host| self.matches(host)),
+            Err(_) => *self == HostPattern::Any,
+        }
+    }
+}
+
+// Middleware to run for each host, in the order they were added.
+//
+// Hosts are matched against the url as it was submitted, before any rewriting.
+#[derive(Debug, Clone, Default)]
+pub struct MiddlewareChain {
+    middleware: Vec<(HostPattern, Arc<dyn Middleware>)>,
+}
+
+impl MiddlewareChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with<M: Middleware + 'static>(self, middleware: M) -> Self {
+        self.with_host("*", middleware)
+    }
+
+    pub fn with_host<M: Middleware + 'static>(mut self, host: &str, middleware: M) -> Self {
+        self.middleware.push((HostPattern::parse(host), Arc::new(middleware)));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.middleware.is_empty()
+    }
+
+    pub fn for_url(&self, url: &str) -> Vec<Arc<dyn Middleware>> {
+        self.middleware
+            .iter()
+            .filter(|(host, _)| host.matches_url(url))
+            .map(|(_, middleware)| middleware.clone())
+            .collect()
+    }
+}
+
+// Runs requests through a middleware chain before handing them to the real transport.
+#[derive(Debug)]
+pub struct MiddlewareTransport {
+    inner: Arc<dyn Transport>,
+    chain: MiddlewareChain,
+}
+
+impl MiddlewareTransport {
+    pub fn new(inner: Arc<dyn Transport>, chain: MiddlewareChain) -> Self {
+        Self { inner, chain }
+    }
+}
+
+#[async_trait]
+impl Transport for MiddlewareTransport {
+    async fn fetch(&self, request: TransportRequest) -> GrabResult<TransportResponse> {
+        self.fetch_with_progress(request, &DownloadProgress::default())
+            .await
+    }
+
+    async fn fetch_with_progress(
+        &self,
+        mut request: TransportRequest,
+        progress: &DownloadProgress,
+    ) -> GrabResult<TransportResponse> {
+        let middleware = self.chain.for_url(&request.url);
+        for middleware in &middleware {
+            middleware.request(&mut request);
+        }
+
+        let response = self
+            .inner
+            .fetch_with_progress(request.clone(), progress)
+            .await;
+
+        // Innermost first, same as unwinding a stack.
+        for middleware in middleware.iter().rev() {
+            middleware.response(&request, &response);
+        }
+        response
+    }
+}
+
+// Swaps the start of matching urls, e.g. to point production urls at a staging server.
+#[derive(Debug, Clone)]
+pub struct RewriteUrl {
+    pub from: String,
+    pub to: String,
+}
+
+impl RewriteUrl {
+    pub fn new(from: &str, to: &str) -> Self {
+        Self {
+            from: from.to_owned(),
+            to: to.to_owned(),
+        }
+    }
+}
+
+impl Middleware for RewriteUrl {
+    fn request(&self, request: &mut TransportRequest) {
+        if let Some(rest) = request.url.strip_prefix(self.from.as_str()) {
+            request.url = format!("{}{}", self.to, rest);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SetHeader {
+    pub name: String,
+    pub value: String,
+}
+
+impl SetHeader {
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            value: value.to_owned(),
+        }
+    }
+}
+
+impl Middleware for SetHeader {
+    fn request(&self, request: &mut TransportRequest) {
+        request
+            .headers
+            .insert(self.name.to_ascii_lowercase(), self.value.clone());
+    }
+}
+
+pub struct BearerToken(pub String);
+
+// Keep tokens out of any logs.
+impl fmt::Debug for BearerToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("BearerToken(..)")
+    }
+}
+
+impl Middleware for BearerToken {
+    fn request(&self, request: &mut TransportRequest) {
+        request
+            .headers
+            .insert("authorization".to_owned(), format!("Bearer {}", self.0));
+    }
+}
+
+type SignFn = dyn Fn(&TransportRequest) -> String + Send + Sync;
+
+// Puts whatever `sign` computes from the final request into `header`, so it should go after anything
+// else that changes the request.
+#[derive(Clone)]
+pub struct SignRequest {
+    header: String,
+    sign: Arc<SignFn>,
+}
+
+impl SignRequest {
+    pub fn new<F>(header: &str, sign: F) -> Self
+    where
+        F: Fn(&TransportRequest) -> String + Send + Sync + 'static,
+    {
+        Self {
+            header: header.to_ascii_lowercase(),
+            sign: Arc::new(sign),
+        }
+    }
+}
+
+impl fmt::Debug for SignRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignRequest")
+            .field("header", &self.header)
+            .finish()
+    }
+}
+
+impl Middleware for SignRequest {
+    fn request(&self, request: &mut TransportRequest) {
+        let signature = (self.sign)(request);
+        request.headers.insert(self.header.clone(), signature);
+    }
+}
+
+// Logs every request and how it went at `info`, header values aren't logged since they can hold tokens.
+#[derive(Debug, Clone, Default)]
+pub struct LogRequests;
+
+impl Middleware for LogRequests {
+    fn request(&self, request: &mut TransportRequest) {
+        let mut headers = request.headers.keys().map(|name| name.as_str()).collect::<Vec<_>>();
+        headers.sort_unstable();
+        log::info!("-> GET {} headers: {:?}", request.url, headers);
+    }
+
+    fn response(&self, request: &TransportRequest, response: &GrabResult<TransportResponse>) {
+        match response {
+            Ok(response) => log::info!(
+                "<- {} {} ({} bytes)",
+                response.status,
+                request.url,
+                response.body.len()
+            ),
+            Err(err) => log::info!("<- failed {}: {}", request.url, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        BearerToken, HostPattern, MiddlewareChain, MiddlewareTransport, RewriteUrl, SetHeader, SignRequest,
+    };
+    use crate::grabber::{MemoryTransport, Transport, TransportRequest};
+    use std::sync::Arc;
+
+    #[test]
+    fn host_patterns() {
+        assert!(HostPattern::parse("*").matches("example.com"));
+        assert!(HostPattern::parse("Example.com").matches("example.COM"));
+        assert!(!HostPattern::parse("example.com").matches("api.example.com"));
+
+        let subdomains = HostPattern::parse("*.example.com");
+        assert!(subdomains.matches("example.com"));
+        assert!(subdomains.matches("staging.api.example.com"));
+        assert!(!subdomains.matches("notexample.com"));
+
+        assert!(subdomains.matches_url("https://api.example.com/home.json?x=1"));
+        assert!(!subdomains.matches_url("not a url"));
+        assert!(HostPattern::Any.matches_url("not a url"));
+    }
+
+    #[tokio::test]
+    async fn applies_per_host() {
+        let mut memory = MemoryTransport::new();
+        memory.insert("https://staging.example.com/home.json", &b"staging"[..]);
+        memory.insert("https://images.example.com/a.jpg", &b"image"[..]);
+        let memory = Arc::new(memory);
+
+        let chain = MiddlewareChain::new()
+            .with(SetHeader::new("X-Device-Id", "device"))
+            .with_host("api.example.com", RewriteUrl::new("https://api.", "https://staging."))
+            .with_host("api.example.com", BearerToken("token".to_owned()))
+            // Sees the rewritten url and the token.
+            .with_host(
+                "api.example.com",
+                SignRequest::new("x-signature", |request| {
+                    format!("{}|{}", request.url, request.header("authorization").unwrap_or(""))
+                }),
+            );
+        let transport = MiddlewareTransport::new(memory.clone(), chain);
+
+        let home = transport
+            .fetch(TransportRequest::new("https://api.example.com/home.json"))
+            .await
+            .expect("staging home");
+        assert_eq!(&home.body[..], b"staging");
+        transport
+            .fetch(TransportRequest::new("https://images.example.com/a.jpg"))
+            .await
+            .expect("image");
+
+        let requests = memory.requests();
+        assert_eq!(requests[0].url, "https://staging.example.com/home.json");
+        assert_eq!(requests[0].header("x-device-id"), Some("device"));
+        assert_eq!(requests[0].header("authorization"), Some("Bearer token"));
+        assert_eq!(
+            requests[0].header("x-signature"),
+            Some("https://staging.example.com/home.json|Bearer token")
+        );
+
+        // Only the header for every host.
+        assert_eq!(requests[1].header("x-device-id"), Some("device"));
+        assert_eq!(requests[1].header("authorization"), None);
+        assert_eq!(requests[1].headers.len(), 1);
+    }
+}
@@ -0,0 +1,912 @@
+pub mod cache_control;
+pub mod decode;
+pub mod disk_cache;
+pub mod dispatch;
+pub mod error;
+pub mod metrics;
+pub mod middleware;
+pub mod progress;
+pub mod retry;
+pub mod safeguards;
+pub mod throttle;
+pub mod transport;
+
+pub use cache_control::CacheControl;
+pub use decode::{Decode, Payload, WeakPayload};
+pub use disk_cache::{CacheEntry, CacheHeaders, CacheMetadata, DiskCache, DiskCacheConfig};
+pub use dispatch::{Command, Limits, Priority};
+pub use error::GrabError;
+pub use metrics::{CacheOutcome, MetricsRecorder, GrabberStats, RequestMetrics, RequestStats, TimingStats};
+pub use middleware::{
+    BearerToken, HostPattern, LogRequests, Middleware, MiddlewareChain, MiddlewareTransport, RewriteUrl,
+    SetHeader, SignRequest,
+};
+pub use progress::DownloadProgress;
+pub use retry::RetryPolicy;
+pub use safeguards::Safeguards;
+pub use throttle::{Bandwidth, ThrottledTransport};
+pub use transport::{
+    strip_query, FileTransport, MemoryTransport, ReqwestTransport, Transport, TransportRequest,
+    TransportResponse,
+};
+
+use bytes::Bytes;
+use flume::{Receiver, Sender};
+use tokio::{task, time};
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::Instant;
+
+pub type GrabResult<T> = std::result::Result<T, GrabError>;
+pub type HttpResponse = Poll<GrabResult<Payload>>;
+type SharedDiskCache = Option<Arc<Mutex<DiskCache>>>;
+
+// Mostly just following https://tokio.rs/tokio/tutorial/shared-state for context.
+//
+// crossbeam-channel might be worth looking at as well, but flume was also a bit nicer with async/await.
+
+// Handle to a submitted request, used to pick the response back up once it is done.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RequestId(usize);
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub id: RequestId,
+    pub url: String,
+    // Host of `url`, worked out once up front since the dispatcher checks it every time a slot frees up.
+    pub host: String,
+    pub priority: Priority,
+    pub decode: Decode,
+    pub progress: Arc<DownloadProgress>,
+    pub submitted: Instant,
+}
+
+#[derive(Debug)]
+pub struct Completion {
+    pub id: RequestId,
+    pub response: GrabResult<Payload>,
+    pub metrics: RequestMetrics,
+}
+
+#[derive(Debug, Clone)]
+pub struct GrabberConfig {
+    pub transport: Arc<dyn Transport>,
+    // Where responses get persisted between runs, `None` to only ever go to the network.
+    pub disk_cache: Option<DiskCacheConfig>,
+    pub retry: RetryPolicy,
+    // Requests actually being fetched at once, everything else waits in the priority queue.
+    pub limits: Limits,
+    // Run over every request before it goes to `transport`.
+    pub middleware: MiddlewareChain,
+    // Caps on response size and decoded images.
+    pub safeguards: Safeguards,
+    // Where to write `HttpGrabber::stats` as json when `dump_stats` gets called on the way out.
+    pub stats_file: Option<PathBuf>,
+}
+
+impl Default for GrabberConfig {
+    fn default() -> Self {
+        Self {
+            transport: Arc::new(ReqwestTransport::new()),
+            disk_cache: Some(DiskCacheConfig::default()),
+            retry: RetryPolicy::default(),
+            limits: Limits::default(),
+            middleware: MiddlewareChain::default(),
+            safeguards: Safeguards::default(),
+            stats_file: None,
+        }
+    }
+}
+
+// Body along with the bits of the response we want to keep around in the disk cache.
+#[derive(Debug, Clone)]
+pub struct FetchedResponse {
+    pub body: Bytes,
+    pub headers: CacheHeaders,
+    // Answer to a conditional request, the body is empty and whatever we had cached is still good.
+    pub not_modified: bool,
+    // Server asked for this not to be cached at all.
+    pub no_store: bool,
+}
+
+// The same url decoded differently is a different response.
+type SharedKey = (String, Decode);
+
+// Don't bother sweeping dead shared responses until there are at least this many.
+const SHARED_SWEEP_MIN: usize = 256;
+
+// One request the worker is actually fetching, on behalf of everything that asked for that url.
+#[derive(Debug)]
+struct Flight {
+    key: SharedKey,
+    // Everything waiting on this along with the priority each of them wants it at, the flight goes at
+    // the highest of them.
+    waiters: HashMap<RequestId, Priority>,
+    priority: Priority,
+    progress: Arc<DownloadProgress>,
+}
+
+// Requests for a url that is already being fetched just wait on that fetch, and once it is done every
+// one of them gets the same payload. Decoded payloads are only held weakly after that, so asking again
+// while anyone still has it is free, and it is freed once the last of them lets go.
+pub struct HttpGrabber {
+    next_id: usize,
+    command_transmit: Sender<Command>,
+    completion_receive: Receiver<Completion>,
+
+    // Responses that have been received from the worker but not taken yet.
+    completed: HashMap<RequestId, GrabResult<Payload>>,
+    // Requests the worker hasn't gotten back to us about, and the flight each is waiting on.
+    waiting: HashMap<RequestId, RequestId>,
+    flights: HashMap<RequestId, Flight>,
+    flight_for: HashMap<SharedKey, RequestId>,
+    shared: HashMap<SharedKey, WeakPayload>,
+    // Sweep dead `shared` responses once there are this many.
+    shared_sweep_at: usize,
+    // Last failure for every url that has given up, cleared when it succeeds again.
+    failures: HashMap<String, GrabError>,
+    metrics: MetricsRecorder,
+    stats_file: Option<PathBuf>,
+}
+
+pub async fn spawn(
+    config: GrabberConfig,
+    command_receive: Receiver<Command>,
+    completion_transmit: Sender<Completion>,
+) {
+    let transport: Arc<dyn Transport> = if config.middleware.is_empty() {
+        config.transport
+    } else {
+        Arc::new(MiddlewareTransport::new(config.transport, config.middleware))
+    };
+
+    let disk_cache = match config.disk_cache {
+        Some(cache_config) => {
+            let directory = cache_config.directory.clone();
+            match task::spawn_blocking(move || DiskCache::open(cache_config)).await {
+                Ok(Ok(disk_cache)) => Some(Arc::new(Mutex::new(disk_cache))),
+                Ok(Err(err)) => {
+                    eprintln!("disk cache disabled, failed to open {:?}: {:?}", directory, err);
+                    None
+                }
+                Err(err) => {
+                    eprintln!("disk cache disabled, open panicked: {:?}", err);
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+
+    let retry = config.retry;
+    let safeguards = Arc::new(config.safeguards);
+    let cache = disk_cache.clone();
+    let shutdown = dispatch::dispatch(command_receive, config.limits, move |request| {
+        process(
+            transport.clone(),
+            disk_cache.clone(),
+            retry.clone(),
+            safeguards.clone(),
+            request,
+            completion_transmit.clone(),
+        )
+    })
+    .await;
+
+    // The cache only writes its index every so often, so catch up on whatever is left.
+    if let Some(cache) = cache {
+        let flushed = task::spawn_blocking(move || lock_cache(&cache).flush()).await;
+        if let Ok(Err(err)) = flushed {
+            eprintln!("failed to write disk cache index: {:?}", err);
+        }
+    }
+    if let Some(done) = shutdown {
+        let _ = done.send(());
+    }
+}
+
+pub async fn process(
+    transport: Arc<dyn Transport>,
+    disk_cache: SharedDiskCache,
+    retry: RetryPolicy,
+    safeguards: Arc<Safeguards>,
+    request: Request,
+    completion_transmit: Sender<Completion>,
+) {
+    let started = Instant::now();
+    let decode = request.decode;
+    let transport_request = TransportRequest::new(&request.url).with_max_bytes(safeguards.max_response_bytes);
+    let fetched = fetch_cached(transport.as_ref(), disk_cache, &retry, transport_request, &request.progress).await;
+
+    let (response, bytes, cache) = match fetched {
+        Ok(CachedBody {
+            body,
+            content_type,
+            cache,
+        }) => {
+            let size = body.len() as u64;
+            let response = task::spawn_blocking(move || {
+                decode.decode_checked(body, content_type.as_deref(), &safeguards)
+            })
+            .await
+            .unwrap_or_else(|err| Err(err.into()));
+            (response, size, Some(cache))
+        }
+        Err(err) => (Err(err), 0, None),
+    };
+
+    let metrics = RequestMetrics {
+        url: request.url,
+        kind: RequestMetrics::kind_of(decode),
+        queue_time: started.duration_since(request.submitted),
+        time_to_first_byte: request
+            .progress
+            .first_response()
+            .map(|first_response| first_response.saturating_duration_since(started)),
+        total_time: started.elapsed(),
+        bytes,
+        network_bytes: request.progress.received(),
+        cache,
+        error: response.as_ref().err().map(GrabError::class),
+    };
+
+    // The grabber being dropped just means nobody cares about the response anymore.
+    let _ = completion_transmit
+        .send_async(Completion {
+            id: request.id,
+            response,
+            metrics,
+        })
+        .await;
+}
+
+// Body handed back by `fetch_cached`, wherever it came from.
+#[derive(Debug, Clone)]
+pub struct CachedBody {
+    pub body: Bytes,
+    pub content_type: Option<String>,
+    pub cache: CacheOutcome,
+}
+
+impl CachedBody {
+    fn from_entry(entry: CacheEntry, cache: CacheOutcome) -> Self {
+        Self {
+            body: entry.body,
+            content_type: entry.metadata.content_type,
+            cache,
+        }
+    }
+}
+
+// Check the disk cache before going out to the network, storing anything new we get back.
+//
+// Stale entries get revalidated with a conditional request, so an unchanged response costs a `304`
+// instead of the whole body.
+pub async fn fetch_cached(
+    transport: &dyn Transport,
+    disk_cache: SharedDiskCache,
+    retry: &RetryPolicy,
+    mut request: TransportRequest,
+    progress: &DownloadProgress,
+) -> GrabResult<CachedBody> {
+    let url = request.url.clone();
+    let disk_cache = match disk_cache {
+        Some(disk_cache) => disk_cache,
+        None => {
+            let response = fetch_with_retry(transport, retry, &request, progress).await?;
+            return Ok(CachedBody {
+                body: response.body,
+                content_type: response.headers.content_type,
+                cache: CacheOutcome::Disabled,
+            });
+        }
+    };
+
+    let cache = disk_cache.clone();
+    let cache_url = url.clone();
+    let cached = task::spawn_blocking(move || lock_cache(&cache).get(&cache_url)).await?;
+
+    if let Some(entry) = &cached {
+        if entry.metadata.is_fresh(disk_cache::unix_now()) {
+            return Ok(CachedBody::from_entry(entry.clone(), CacheOutcome::Hit));
+        }
+
+        request = entry.metadata.conditional(request);
+    }
+
+    let response = match fetch_with_retry(transport, retry, &request, progress).await {
+        Ok(response) => response,
+        Err(err) => match cached {
+            Some(entry) => {
+                // Out of date is still better than nothing.
+                eprintln!("revalidating {} failed ({}), using the stale copy", url, err);
+                return Ok(CachedBody::from_entry(entry, CacheOutcome::Stale));
+            }
+            None => return Err(err),
+        },
+    };
+
+    if response.not_modified {
+        // Only missing if the entry got evicted while we were asking about it.
+        let entry = cached.ok_or(GrabError::Status {
+            status: 304,
+            retry_after: None,
+        })?;
+
+        let max_age = response.headers.max_age;
+        task::spawn_blocking(move || {
+            if let Err(err) = lock_cache(&disk_cache).revalidate(&url, max_age) {
+                eprintln!("failed to update cached {}: {:?}", url, err);
+            }
+        })
+        .await?;
+
+        return Ok(CachedBody::from_entry(entry, CacheOutcome::Revalidated));
+    }
+
+    let body = response.body.clone();
+    let content_type = response.headers.content_type.clone();
+    if !response.no_store {
+        task::spawn_blocking(move || {
+            let FetchedResponse { body, headers, .. } = response;
+            if let Err(err) = lock_cache(&disk_cache).insert(&url, &body, headers) {
+                eprintln!("failed to cache {}: {:?}", url, err);
+            }
+        })
+        .await?;
+    }
+
+    Ok(CachedBody {
+        body,
+        content_type,
+        cache: CacheOutcome::Miss,
+    })
+}
+
+fn lock_cache(disk_cache: &Mutex<DiskCache>) -> std::sync::MutexGuard<'_, DiskCache> {
+    // A panic while holding the lock doesn't leave the cache in a state we can't keep using.
+    disk_cache.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+pub async fn fetch_with_retry(
+    transport: &dyn Transport,
+    retry: &RetryPolicy,
+    request: &TransportRequest,
+    progress: &DownloadProgress,
+) -> GrabResult<FetchedResponse> {
+    let mut attempt = 0;
+    loop {
+        let response = match time::timeout(retry.timeout, fetch(transport, request.clone(), progress)).await {
+            Ok(response) => response,
+            Err(_) => Err(GrabError::Timeout),
+        };
+
+        let error = match response {
+            Ok(response) => return Ok(response),
+            Err(error) => error,
+        };
+
+        match retry.delay_for(attempt, &error) {
+            Some(delay) => {
+                eprintln!(
+                    "fetching {} failed ({}), retrying in {:?}",
+                    request.url, error, delay
+                );
+                time::sleep(delay).await;
+                attempt += 1;
+            }
+            None => return Err(error),
+        }
+    }
+}
+
+pub async fn fetch(
+    transport: &dyn Transport,
+    request: TransportRequest,
+    progress: &DownloadProgress,
+) -> GrabResult<FetchedResponse> {
+    // Backends that don't stream only find out once the whole body is in.
+    let response = transport.fetch_with_progress(request.clone(), progress).await?;
+    request.check_size(response.body.len() as u64)?;
+
+    let not_modified = response.status == 304;
+    if !response.is_success() && !not_modified {
+        return Err(GrabError::Status {
+            status: response.status,
+            retry_after: response
+                .header("retry-after")
+                .and_then(retry::parse_retry_after),
+        });
+    }
+
+    let header = |name: &str| response.header(name).map(|value| value.to_owned());
+    let cache_control = response
+        .header("cache-control")
+        .map(CacheControl::parse)
+        .unwrap_or_default();
+
+    Ok(FetchedResponse {
+        headers: CacheHeaders {
+            content_type: header("content-type"),
+            etag: header("etag"),
+            last_modified: header("last-modified"),
+            max_age: cache_control.freshness(),
+        },
+        not_modified,
+        no_store: cache_control.no_store,
+        body: response.body,
+    })
+}
+
+impl HttpGrabber {
+    pub fn new() -> HttpGrabber {
+        HttpGrabber::with_config(GrabberConfig::default())
+    }
+
+    pub fn with_config(config: GrabberConfig) -> HttpGrabber {
+        let stats_file = config.stats_file.clone();
+        let (command_transmit, command_receive) = flume::unbounded();
+        let (completion_transmit, completion_receive) = flume::unbounded();
+
+        task::spawn(async move {
+            spawn(config, command_receive, completion_transmit).await;
+        });
+
+        HttpGrabber {
+            next_id: 0,
+            command_transmit,
+            completion_receive,
+            completed: HashMap::new(),
+            waiting: HashMap::new(),
+            flights: HashMap::new(),
+            flight_for: HashMap::new(),
+            shared: HashMap::new(),
+            shared_sweep_at: SHARED_SWEEP_MIN,
+            failures: HashMap::new(),
+            metrics: MetricsRecorder::default(),
+            stats_file,
+        }
+    }
+
+    // Queue up a url to be fetched, this never blocks so it is fine to call from the event loop.
+    pub fn submit(&mut self, url: String) -> RequestId {
+        self.submit_with_priority(url, Priority::default())
+    }
+
+    pub fn submit_with_priority(&mut self, url: String, priority: Priority) -> RequestId {
+        self.submit_decoded(url, priority, Decode::Raw)
+    }
+
+    // Same as `submit_with_priority`, but the response comes back already decoded as `decode`.
+    pub fn submit_decoded(&mut self, url: String, priority: Priority, decode: Decode) -> RequestId {
+        let id = self.next_request_id();
+        let key = (url, decode);
+
+        if let Some(payload) = self.shared.get(&key).and_then(WeakPayload::upgrade) {
+            self.metrics.record_shared();
+            self.completed.insert(id, Ok(payload));
+            return id;
+        }
+
+        if let Some(&flight_id) = self.flight_for.get(&key) {
+            self.metrics.record_shared();
+            self.waiting.insert(id, flight_id);
+            if let Some(flight) = self.flights.get_mut(&flight_id) {
+                flight.waiters.insert(id, priority);
+            }
+            self.update_flight_priority(flight_id);
+            return id;
+        }
+
+        let flight_id = self.next_request_id();
+        let progress = Arc::new(DownloadProgress::default());
+        let request = Request {
+            id: flight_id,
+            url: key.0.clone(),
+            host: dispatch::host_of(&key.0),
+            priority,
+            decode,
+            progress: progress.clone(),
+            submitted: Instant::now(),
+        };
+        if let Err(err) = self.command_transmit.send(Command::Submit(request)) {
+            // Worker is gone, so just complete the request immediately with the error.
+            self.completed.insert(
+                id,
+                Err(GrabError::Worker(format!("worker stopped: {}", err))),
+            );
+            return id;
+        }
+
+        let mut waiters = HashMap::new();
+        waiters.insert(id, priority);
+        self.flight_for.insert(key.clone(), flight_id);
+        self.flights.insert(
+            flight_id,
+            Flight {
+                key,
+                waiters,
+                priority,
+                progress,
+            },
+        );
+        self.waiting.insert(id, flight_id);
+
+        id
+    }
+
+    fn next_request_id(&mut self) -> RequestId {
+        let id = RequestId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    // Change where a request sits in the queue, does nothing once it has started or finished.
+    pub fn set_priority(&mut self, id: RequestId, priority: Priority) {
+        let flight_id = match self.waiting.get(&id) {
+            Some(flight_id) => *flight_id,
+            None => return,
+        };
+
+        if let Some(flight) = self.flights.get_mut(&flight_id) {
+            flight.waiters.insert(id, priority);
+        }
+        self.update_flight_priority(flight_id);
+    }
+
+    fn update_flight_priority(&mut self, flight_id: RequestId) {
+        let flight = match self.flights.get_mut(&flight_id) {
+            Some(flight) => flight,
+            None => return,
+        };
+
+        let wanted = flight.waiters.values().copied().max().unwrap_or(flight.priority);
+        if wanted != flight.priority {
+            flight.priority = wanted;
+            let _ = self.command_transmit.send(Command::Prioritise(flight_id, wanted));
+        }
+    }
+
+    // Stop caring about a request, whatever it comes back with is thrown away. The fetch itself only
+    // gets cancelled once nothing else is waiting on it.
+    pub fn cancel(&mut self, id: RequestId) {
+        self.completed.remove(&id);
+
+        let flight_id = match self.waiting.remove(&id) {
+            Some(flight_id) => flight_id,
+            None => return,
+        };
+        self.metrics.record_cancel();
+
+        let abandoned = match self.flights.get_mut(&flight_id) {
+            Some(flight) => {
+                flight.waiters.remove(&id);
+                flight.waiters.is_empty()
+            }
+            None => false,
+        };
+
+        if abandoned {
+            if let Some(flight) = self.flights.remove(&flight_id) {
+                self.flight_for.remove(&flight.key);
+            }
+            let _ = self.command_transmit.send(Command::Cancel(flight_id));
+        } else {
+            self.update_flight_priority(flight_id);
+        }
+    }
+
+    // Pull everything the worker has finished so far without waiting on anything.
+    pub fn drain_completions(&mut self) {
+        let completions = self.completion_receive.try_iter().collect::<Vec<_>>();
+        for completion in completions {
+            self.complete(completion);
+        }
+    }
+
+    // Hands a finished flight out to everything waiting on it.
+    fn complete(&mut self, completion: Completion) {
+        // Anything without a flight anymore was cancelled while it was finishing up.
+        let flight = match self.flights.remove(&completion.id) {
+            Some(flight) => flight,
+            None => return,
+        };
+        self.flight_for.remove(&flight.key);
+        self.metrics.record(completion.metrics);
+
+        let (url, _) = &flight.key;
+        match &completion.response {
+            Ok(payload) => {
+                self.failures.remove(url);
+                if let Some(weak) = payload.downgrade() {
+                    self.shared.insert(flight.key.clone(), weak);
+                }
+            }
+            Err(err) => {
+                eprintln!("giving up on {}: {}", url, err);
+                self.failures.insert(url.clone(), err.clone());
+            }
+        }
+
+        for waiter in flight.waiters.keys() {
+            self.waiting.remove(waiter);
+            self.completed.insert(*waiter, completion.response.clone());
+        }
+
+        if self.shared.len() >= self.shared_sweep_at {
+            self.shared.retain(|_, weak| weak.is_alive());
+            self.shared_sweep_at = (self.shared.len() * 2).max(SHARED_SWEEP_MIN);
+        }
+    }
+
+    // How much of a pending request's body has come in, nothing once it is done.
+    pub fn progress(&self, id: RequestId) -> Option<&DownloadProgress> {
+        let flight = self.flights.get(self.waiting.get(&id)?)?;
+        Some(flight.progress.as_ref())
+    }
+
+    pub fn stats(&self) -> GrabberStats {
+        self.metrics.snapshot()
+    }
+
+    // Timings and such for the last time `url` finished.
+    pub fn request_metrics(&self, url: &str) -> Option<&RequestMetrics> {
+        self.metrics.latest(url)
+    }
+
+    // Writes `stats` out to the configured `stats_file`, if there is one.
+    pub fn dump_stats(&mut self) {
+        self.drain_completions();
+
+        let path = match &self.stats_file {
+            Some(path) => path,
+            None => return,
+        };
+
+        let written = serde_json::to_vec_pretty(&self.stats())
+            .map_err(anyhow::Error::from)
+            .and_then(|json| std::fs::write(path, json).map_err(anyhow::Error::from));
+        match written {
+            Ok(()) => println!("wrote grabber stats to {:?}", path),
+            Err(err) => eprintln!("failed to write grabber stats to {:?}: {:?}", path, err),
+        }
+    }
+
+    // Stops fetching and writes out what the disk cache hasn't yet. The event loop never returns, so
+    // nothing gets dropped on exit and this has to be called instead. Waits a second at most.
+    pub fn shutdown(&mut self) {
+        let (done_transmit, done_receive) = flume::bounded(1);
+        if self.command_transmit.send(Command::Shutdown(done_transmit)).is_ok() {
+            let _ = done_receive.recv_timeout(std::time::Duration::from_secs(1));
+        }
+    }
+
+    // Why a url failed the last time it was grabbed, if it did.
+    pub fn failure(&self, url: &str) -> Option<&GrabError> {
+        self.failures.get(url)
+    }
+
+    // Take the response for a request if it is done, the response can only be taken once.
+    pub fn try_take(&mut self, id: RequestId) -> HttpResponse {
+        self.drain_completions();
+
+        match self.completed.remove(&id) {
+            Some(response) => Poll::Ready(response),
+            None => Poll::Pending,
+        }
+    }
+
+    // Waits for the response to a request, for when there's no event loop to keep polling from.
+    pub async fn take(&mut self, id: RequestId) -> GrabResult<Payload> {
+        loop {
+            if let Poll::Ready(response) = self.try_take(id) {
+                return response;
+            }
+            if !self.waiting.contains_key(&id) {
+                return Err(GrabError::Worker("request was cancelled or already taken".to_owned()));
+            }
+
+            match self.completion_receive.recv_async().await {
+                Ok(completion) => self.complete(completion),
+                Err(err) => return Err(GrabError::Worker(format!("worker stopped: {}", err))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        fetch_cached, CacheOutcome, Decode, DiskCache, DiskCacheConfig, DownloadProgress, GrabError, GrabberConfig,
+        HttpGrabber, MemoryTransport, Priority, RequestId, RetryPolicy, Safeguards, TransportRequest,
+        TransportResponse,
+    };
+    use crate::fixtures;
+    use bytes::Bytes;
+    use image::RgbaImage;
+    use std::sync::{Arc, Mutex};
+    use std::task::Poll;
+    use std::time::Duration;
+
+    async fn take_image(grabber: &mut HttpGrabber, id: RequestId) -> Arc<RgbaImage> {
+        for _ in 0..500 {
+            if let Poll::Ready(response) = grabber.try_take(id) {
+                return response.and_then(|payload| payload.into_image()).expect("image");
+            }
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+        panic!("request never finished");
+    }
+
+    #[tokio::test]
+    async fn rejects_unsafe_responses() {
+        let mut transport = MemoryTransport::new();
+        transport.insert("big.png", fixtures::png_bytes());
+        transport.insert_response(
+            "error.png",
+            TransportResponse::ok(Bytes::from_static(b"<html>")).with_header("content-type", "text/html"),
+        );
+        transport.insert("huge", vec![0u8; 2048]);
+        let mut grabber = HttpGrabber::with_config(GrabberConfig {
+            transport: Arc::new(transport),
+            disk_cache: None,
+            retry: RetryPolicy::never(),
+            safeguards: Safeguards {
+                max_response_bytes: 1024,
+                max_image_dimension: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        let requests = vec![
+            grabber.submit_decoded("big.png".to_owned(), Priority::Visible, Decode::Image),
+            grabber.submit_decoded("error.png".to_owned(), Priority::Visible, Decode::Image),
+            grabber.submit_decoded("huge".to_owned(), Priority::Visible, Decode::Raw),
+        ];
+
+        let mut errors = Vec::new();
+        for id in requests {
+            loop {
+                if let Poll::Ready(response) = grabber.try_take(id) {
+                    errors.push(response.expect_err("unsafe response"));
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(2)).await;
+            }
+        }
+
+        assert_eq!(
+            errors,
+            vec![
+                GrabError::ImageTooLarge { width: 2, height: 2 },
+                GrabError::UnexpectedContentType("text/html".to_owned()),
+                GrabError::ResponseTooLarge { limit: 1024 },
+            ]
+        );
+        assert_eq!(grabber.failure("huge"), Some(&errors[2]));
+    }
+
+    #[tokio::test]
+    async fn shares_duplicate_requests() {
+        let mut transport = MemoryTransport::new();
+        transport.insert("a.png", fixtures::png_bytes());
+        let transport = Arc::new(transport);
+        let mut grabber = HttpGrabber::with_config(GrabberConfig {
+            transport: transport.clone(),
+            disk_cache: None,
+            retry: RetryPolicy::never(),
+            ..Default::default()
+        });
+        let url = || "a.png".to_owned();
+
+        let first = grabber.submit_decoded(url(), Priority::Background, Decode::Image);
+        let second = grabber.submit_decoded(url(), Priority::Focused, Decode::Image);
+        let cancelled = grabber.submit_decoded(url(), Priority::Visible, Decode::Image);
+        // Giving up on one of them leaves the download going for the others.
+        grabber.cancel(cancelled);
+
+        let first = take_image(&mut grabber, first).await;
+        let second = take_image(&mut grabber, second).await;
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(transport.requested().len(), 1);
+
+        // Still held, so asking again doesn't go anywhere near the worker.
+        drop(first);
+        let again = grabber.submit_decoded(url(), Priority::Visible, Decode::Image);
+        assert!(matches!(grabber.try_take(again), Poll::Ready(Ok(_))));
+        assert_eq!(transport.requested().len(), 1);
+
+        // Once everyone lets go it has to be downloaded again.
+        drop(second);
+        let after = grabber.submit_decoded(url(), Priority::Visible, Decode::Image);
+        take_image(&mut grabber, after).await;
+        assert_eq!(transport.requested().len(), 2);
+
+        let stats = grabber.stats();
+        assert_eq!(stats.overall.requests, 2);
+        assert_eq!(stats.shared, 3);
+        assert_eq!(stats.cancelled, 1);
+    }
+
+    #[tokio::test]
+    async fn revalidates_stale_entries() {
+        let directory = std::env::temp_dir().join(format!("dss-revalidate-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let disk_cache = DiskCache::open(DiskCacheConfig {
+            directory: directory.clone(),
+            max_bytes: 1024,
+        })
+        .expect("open cache");
+        let disk_cache = Some(Arc::new(Mutex::new(disk_cache)));
+
+        let mut transport = MemoryTransport::new();
+        transport.insert_response(
+            "home",
+            TransportResponse::ok(Bytes::from_static(b"home"))
+                .with_header("etag", "\"v1\"")
+                .with_header("cache-control", "no-cache"),
+        );
+        transport.insert_response(
+            "image",
+            TransportResponse::ok(Bytes::from_static(b"image")).with_header("cache-control", "max-age=3600"),
+        );
+
+        let retry = RetryPolicy::never();
+        let mut outcomes = Vec::new();
+        for _ in 0..2 {
+            for url in &["home", "image"] {
+                let progress = DownloadProgress::default();
+                let request = TransportRequest::new(url);
+                let cached = fetch_cached(&transport, disk_cache.clone(), &retry, request, &progress)
+                    .await
+                    .expect("fetch");
+                assert_eq!(&cached.body[..], url.as_bytes());
+                outcomes.push(cached.cache);
+            }
+        }
+        assert_eq!(
+            outcomes,
+            vec![CacheOutcome::Miss, CacheOutcome::Miss, CacheOutcome::Revalidated, CacheOutcome::Hit]
+        );
+
+        // Fresh image comes straight from disk, home gets asked about again but not resent.
+        let requests = transport.requests();
+        let urls = requests.iter().map(|request| request.url.as_str()).collect::<Vec<_>>();
+        assert_eq!(urls, vec!["home", "image", "home"]);
+        assert_eq!(requests[0].header("if-none-match"), None);
+        assert_eq!(requests[2].header("if-none-match"), Some("\"v1\""));
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    // Blocks waiting on the grabber's own tasks, so they need other threads to run on.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn shutdown_writes_cache_index() {
+        let directory = std::env::temp_dir().join(format!("dss-shutdown-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let mut transport = MemoryTransport::new();
+        transport.insert("home", &b"home"[..]);
+        let mut grabber = HttpGrabber::with_config(GrabberConfig {
+            transport: Arc::new(transport),
+            disk_cache: Some(DiskCacheConfig {
+                directory: directory.clone(),
+                max_bytes: 1024,
+            }),
+            retry: RetryPolicy::never(),
+            ..Default::default()
+        });
+
+        let request = grabber.submit("home".to_owned());
+        grabber.take(request).await.expect("home");
+        grabber.shutdown();
+
+        let index = std::fs::read_to_string(directory.join("index.json")).expect("index");
+        assert!(index.contains("\"url\":\"home\""), "{}", index);
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+}
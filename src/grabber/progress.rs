@@ -0,0 +1,85 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+// Body bytes received so far for a request, shared between the worker streaming it in and whatever
+// wants to show how far along it is.
+//
+// Relaxed atomics are plenty, this is only ever used for display.
+#[derive(Debug, Default)]
+pub struct DownloadProgress {
+    received: AtomicU64,
+    // Zero when there was no `Content-Length`.
+    total: AtomicU64,
+    // When the first response of any attempt started coming in.
+    first_response: Mutex<Option<Instant>>,
+}
+
+impl DownloadProgress {
+    // Called once a response starts coming in, again for every retry.
+    pub fn start(&self, total: Option<u64>) {
+        self.received.store(0, Ordering::Relaxed);
+        self.total.store(total.unwrap_or(0), Ordering::Relaxed);
+
+        let mut first_response = self
+            .first_response
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        first_response.get_or_insert_with(Instant::now);
+    }
+
+    pub fn add(&self, bytes: u64) {
+        self.received.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn received(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    pub fn first_response(&self) -> Option<Instant> {
+        *self
+            .first_response
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn total(&self) -> Option<u64> {
+        match self.total.load(Ordering::Relaxed) {
+            0 => None,
+            total => Some(total),
+        }
+    }
+
+    // Between 0 and 1, `None` when we don't know how big the body is.
+    pub fn fraction(&self) -> Option<f32> {
+        let total = self.total()?;
+        Some((self.received() as f64 / total as f64).min(1.0) as f32)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DownloadProgress;
+
+    #[test]
+    fn reports_fraction() {
+        let progress = DownloadProgress::default();
+        assert_eq!(progress.fraction(), None);
+        assert_eq!(progress.first_response(), None);
+
+        progress.start(Some(200));
+        let first_response = progress.first_response();
+        assert!(first_response.is_some());
+        progress.add(50);
+        assert_eq!(progress.fraction(), Some(0.25));
+        progress.add(500);
+        assert_eq!(progress.fraction(), Some(1.0));
+
+        // Retrying starts over.
+        progress.start(None);
+        progress.add(10);
+        assert_eq!(progress.first_response(), first_response);
+        assert_eq!(progress.received(), 10);
+        assert_eq!(progress.fraction(), None);
+    }
+}
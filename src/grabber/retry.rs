@@ -0,0 +1,133 @@
+use rand::Rng;
+
+use std::time::{Duration, SystemTime};
+
+use super::GrabError;
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    // Including the first attempt, so 1 means never retry.
+    pub max_attempts: u32,
+    // Delay before the first retry, doubled for every attempt after that.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    // Servers asking us to wait longer than this get treated as a failure instead.
+    pub max_retry_after: Duration,
+    // Per attempt, covers connecting up through reading the whole body.
+    pub timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(10),
+            max_retry_after: Duration::from_secs(60),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn never() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    // Exponential backoff capped at `max_delay`, with the top half jittered so a whole row of
+    // failed tiles doesn't come back at the server at the exact same time.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.min(31);
+        let delay = self
+            .base_delay
+            .checked_mul(1 << exponent)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+
+        let half = delay / 2;
+        let jitter = rand::thread_rng().gen_range(0..=half.as_millis() as u64);
+        half + Duration::from_millis(jitter)
+    }
+
+    // How long to wait before trying again after `attempt` (starting at 0) failed with `error`,
+    // `None` meaning we should give up.
+    pub fn delay_for(&self, attempt: u32, error: &GrabError) -> Option<Duration> {
+        if attempt + 1 >= self.max_attempts || !error.is_transient() {
+            return None;
+        }
+
+        match error.retry_after() {
+            Some(retry_after) if retry_after > self.max_retry_after => None,
+            Some(retry_after) => Some(retry_after),
+            None => Some(self.backoff(attempt)),
+        }
+    }
+}
+
+// `Retry-After` is either a number of seconds or an http date.
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_retry_after, RetryPolicy};
+    use crate::grabber::GrabError;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn backoff_stays_in_bounds() {
+        let policy = RetryPolicy::default();
+
+        for attempt in 0..40 {
+            let delay = policy.backoff(attempt);
+            let cap = (policy.base_delay * 2u32.pow(attempt.min(10))).min(policy.max_delay);
+            assert!(delay >= cap / 2, "attempt {} delay {:?}", attempt, delay);
+            assert!(delay <= cap, "attempt {} delay {:?}", attempt, delay);
+        }
+    }
+
+    #[test]
+    fn gives_up() {
+        let policy = RetryPolicy::default();
+        let not_found = GrabError::Status {
+            status: 404,
+            retry_after: None,
+        };
+
+        assert!(policy.delay_for(0, &GrabError::Timeout).is_some());
+        assert!(policy.delay_for(policy.max_attempts - 1, &GrabError::Timeout).is_none());
+        assert!(policy.delay_for(0, &not_found).is_none());
+        assert!(RetryPolicy::never().delay_for(0, &GrabError::Timeout).is_none());
+    }
+
+    #[test]
+    fn honours_retry_after() {
+        let policy = RetryPolicy::default();
+        let throttled = |seconds| GrabError::Status {
+            status: 429,
+            retry_after: Some(Duration::from_secs(seconds)),
+        };
+
+        assert_eq!(policy.delay_for(0, &throttled(3)), Some(Duration::from_secs(3)));
+        assert_eq!(policy.delay_for(0, &throttled(3600)), None);
+
+        assert_eq!(parse_retry_after(" 120 "), Some(Duration::from_secs(120)));
+        let later = SystemTime::now() + Duration::from_secs(30);
+        let parsed = parse_retry_after(&httpdate::fmt_http_date(later)).expect("http date");
+        assert!(parsed <= Duration::from_secs(30) && parsed >= Duration::from_secs(28));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+}
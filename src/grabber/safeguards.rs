@@ -0,0 +1,135 @@
+use std::io::Cursor;
+
+use super::{Decode, GrabError, GrabResult};
+
+// Caps on what we are willing to download and decode, so a broken or hostile response only fails that
+// one request instead of taking the whole process down with it.
+#[derive(Debug, Clone)]
+pub struct Safeguards {
+    // Checked against `Content-Length` up front and again while the body streams in.
+    pub max_response_bytes: u64,
+    // Largest width or height an image can claim, checked from the header before decoding anything.
+    pub max_image_dimension: u32,
+    // Decoded images are 4 bytes a pixel, so this bounds the memory a single image can take.
+    pub max_image_pixels: u64,
+    // Refuse to decode responses whose content type says they are something else.
+    pub check_content_type: bool,
+}
+
+impl Default for Safeguards {
+    fn default() -> Self {
+        Self {
+            max_response_bytes: 16 * 1024 * 1024,
+            max_image_dimension: 8192,
+            max_image_pixels: 4096 * 4096,
+            check_content_type: true,
+        }
+    }
+}
+
+impl Safeguards {
+    // Responses without a content type, or a generic binary one, get the benefit of the doubt.
+    pub fn check_content_type(&self, decode: Decode, content_type: Option<&str>) -> GrabResult<()> {
+        let content_type = match content_type {
+            Some(content_type) if self.check_content_type => content_type,
+            _ => return Ok(()),
+        };
+
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+        let expected = match decode {
+            Decode::Raw => true,
+            Decode::Image | Decode::CroppedImage(_) => mime.starts_with("image/"),
+            Decode::Home | Decode::RefSet => {
+                mime == "application/json" || mime == "text/json" || mime.ends_with("+json")
+            }
+        };
+
+        if expected || mime.is_empty() || mime == "application/octet-stream" {
+            Ok(())
+        } else {
+            Err(GrabError::UnexpectedContentType(mime))
+        }
+    }
+
+    pub fn check_image_dimensions(&self, width: u32, height: u32) -> GrabResult<()> {
+        if width > self.max_image_dimension || height > self.max_image_dimension {
+            return Err(GrabError::ImageTooLarge { width, height });
+        }
+
+        let pixels = width as u64 * height as u64;
+        if pixels > self.max_image_pixels {
+            return Err(GrabError::TooManyPixels {
+                pixels,
+                limit: self.max_image_pixels,
+            });
+        }
+
+        Ok(())
+    }
+
+    // Only reads as far as the image header, so this is cheap even for something huge.
+    pub fn check_image(&self, bytes: &[u8]) -> GrabResult<()> {
+        let (width, height) = image::io::Reader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .map_err(|err| GrabError::Decode(err.to_string()))?
+            .into_dimensions()
+            .map_err(|err| GrabError::Decode(err.to_string()))?;
+
+        self.check_image_dimensions(width, height)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Safeguards;
+    use crate::fixtures;
+    use crate::grabber::{Decode, GrabError};
+
+    #[test]
+    fn content_types() {
+        let safeguards = Safeguards::default();
+        let check = |decode, content_type| safeguards.check_content_type(decode, content_type);
+
+        assert!(check(Decode::Image, Some("image/jpeg")).is_ok());
+        assert!(check(Decode::Image, None).is_ok());
+        assert!(check(Decode::Image, Some("application/octet-stream")).is_ok());
+        assert_eq!(
+            check(Decode::Image, Some("text/html; charset=utf-8")),
+            Err(GrabError::UnexpectedContentType("text/html".to_owned()))
+        );
+        assert!(check(Decode::Home, Some("application/json; charset=utf-8")).is_ok());
+        assert!(check(Decode::RefSet, Some("image/png")).is_err());
+        assert!(check(Decode::Raw, Some("text/html")).is_ok());
+
+        let unchecked = Safeguards {
+            check_content_type: false,
+            ..Default::default()
+        };
+        assert!(unchecked.check_content_type(Decode::Image, Some("text/html")).is_ok());
+    }
+
+    #[test]
+    fn image_limits() {
+        let safeguards = Safeguards {
+            max_image_dimension: 100,
+            max_image_pixels: 50 * 50,
+            ..Default::default()
+        };
+
+        assert!(safeguards.check_image(&fixtures::png_bytes()).is_ok());
+        assert_eq!(
+            safeguards.check_image_dimensions(101, 1),
+            Err(GrabError::ImageTooLarge { width: 101, height: 1 })
+        );
+        assert_eq!(
+            safeguards.check_image_dimensions(100, 100),
+            Err(GrabError::TooManyPixels { pixels: 10_000, limit: 2500 })
+        );
+        assert!(matches!(safeguards.check_image(b"not an image"), Err(GrabError::Decode(_))));
+    }
+}
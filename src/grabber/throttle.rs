@@ -0,0 +1,176 @@
+use async_trait::async_trait;
+use tokio::time::{self, Instant};
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::{DownloadProgress, GrabResult, Transport, TransportRequest, TransportResponse};
+
+// Bytes handed out at a time when pacing a body that is already in memory.
+const SIMULATED_CHUNK: usize = 16 * 1024;
+
+// Token bucket shared between every request it is handed to, so it caps them all together.
+//
+// Holds up to a second's worth of bytes, anything over that has to wait for the bucket to refill.
+#[derive(Clone)]
+pub struct Bandwidth {
+    bytes_per_second: u64,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+struct Bucket {
+    // Goes negative when bytes have been taken that haven't been paid for yet.
+    available: f64,
+    updated: Instant,
+}
+
+impl Bandwidth {
+    pub fn new(bytes_per_second: u64) -> Self {
+        let bytes_per_second = bytes_per_second.max(1);
+        Self {
+            bytes_per_second,
+            bucket: Arc::new(Mutex::new(Bucket {
+                available: bytes_per_second as f64,
+                updated: Instant::now(),
+            })),
+        }
+    }
+
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bytes_per_second
+    }
+
+    // Takes `bytes` out of the bucket right away, returning how long to wait before using them.
+    //
+    // Going into debt rather than waiting for the bucket to refill first means whoever asks first is
+    // also first to go, no matter how big their chunk is.
+    pub fn reserve(&self, bytes: u64) -> Duration {
+        let rate = self.bytes_per_second as f64;
+        let mut bucket = self
+            .bucket
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let now = Instant::now();
+        let refilled = now.duration_since(bucket.updated).as_secs_f64() * rate;
+        bucket.available = (bucket.available + refilled).min(rate);
+        bucket.updated = now;
+        bucket.available -= bytes as f64;
+
+        if bucket.available >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.available / rate)
+        }
+    }
+
+    pub async fn take(&self, bytes: u64) {
+        let wait = self.reserve(bytes);
+        if !wait.is_zero() {
+            time::sleep(wait).await;
+        }
+    }
+}
+
+impl fmt::Debug for Bandwidth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bandwidth")
+            .field("bytes_per_second", &self.bytes_per_second)
+            .finish()
+    }
+}
+
+// Pretends whatever is behind it is a slow connection, for seeing how things behave on bad networks
+// without needing one.
+//
+// The response is fetched at full speed and then trickled out, so this works the same in front of
+// files, bundles or the real network.
+#[derive(Debug)]
+pub struct ThrottledTransport {
+    inner: Arc<dyn Transport>,
+    // Extra time before anything comes back, like a round trip.
+    latency: Duration,
+    bandwidth: Option<Bandwidth>,
+}
+
+impl ThrottledTransport {
+    pub fn new(inner: Arc<dyn Transport>, latency: Duration, bandwidth: Option<Bandwidth>) -> Self {
+        Self {
+            inner,
+            latency,
+            bandwidth,
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for ThrottledTransport {
+    async fn fetch(&self, request: TransportRequest) -> GrabResult<TransportResponse> {
+        self.fetch_with_progress(request, &DownloadProgress::default())
+            .await
+    }
+
+    async fn fetch_with_progress(
+        &self,
+        request: TransportRequest,
+        progress: &DownloadProgress,
+    ) -> GrabResult<TransportResponse> {
+        time::sleep(self.latency).await;
+        let response = self.inner.fetch(request).await?;
+
+        progress.start(Some(response.body.len() as u64));
+        for chunk in response.body.chunks(SIMULATED_CHUNK) {
+            if let Some(bandwidth) = &self.bandwidth {
+                bandwidth.take(chunk.len() as u64).await;
+            }
+            progress.add(chunk.len() as u64);
+        }
+
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Bandwidth, ThrottledTransport};
+    use crate::grabber::{DownloadProgress, MemoryTransport, Transport, TransportRequest};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    #[tokio::test(start_paused = true)]
+    async fn token_bucket() {
+        let bandwidth = Bandwidth::new(1000);
+        // Starts with a second's worth.
+        assert_eq!(bandwidth.reserve(1000), Duration::ZERO);
+        assert_eq!(bandwidth.reserve(500), Duration::from_millis(500));
+        // Queued up behind the last one.
+        assert_eq!(bandwidth.reserve(500), Duration::from_secs(1));
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        // Never refills past a second's worth.
+        assert_eq!(bandwidth.reserve(1000), Duration::ZERO);
+        assert_eq!(bandwidth.reserve(100), Duration::from_millis(100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn simulates_slow_connection() {
+        let mut memory = MemoryTransport::new();
+        memory.insert("a", vec![0u8; 64 * 1024]);
+        let bandwidth = Bandwidth::new(16 * 1024);
+        let transport = ThrottledTransport::new(Arc::new(memory), Duration::from_millis(200), Some(bandwidth));
+
+        let started = Instant::now();
+        let progress = DownloadProgress::default();
+        let response = transport
+            .fetch_with_progress(TransportRequest::new("a"), &progress)
+            .await
+            .expect("throttled response");
+
+        assert_eq!(response.body.len(), 64 * 1024);
+        assert_eq!(progress.fraction(), Some(1.0));
+        // Latency, then the first second's worth is free and the other three have to wait.
+        assert_eq!(started.elapsed(), Duration::from_millis(3200));
+    }
+}
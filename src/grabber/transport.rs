@@ -0,0 +1,638 @@
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use percent_encoding::percent_decode_str;
+use reqwest::{Client, Proxy, Url};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::{Bandwidth, DownloadProgress, GrabError, GrabResult, HostPattern};
+
+#[derive(Debug, Clone)]
+pub struct TransportRequest {
+    pub url: String,
+    // Header names are always lowercase.
+    pub headers: HashMap<String, String>,
+    // Give up with `GrabError::ResponseTooLarge` once the body goes over this. Backends that stream
+    // should stop reading as soon as they know, everything else is checked once the body is in.
+    pub max_bytes: Option<u64>,
+}
+
+impl TransportRequest {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+            headers: HashMap::new(),
+            max_bytes: None,
+        }
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    // Whether a body of `size` bytes, or one that has got that far so far, is still within `max_bytes`.
+    pub fn check_size(&self, size: u64) -> GrabResult<()> {
+        match self.max_bytes {
+            Some(limit) if size > limit => Err(GrabError::ResponseTooLarge { limit }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers
+            .insert(name.to_ascii_lowercase(), value.to_owned());
+        self
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(|value| value.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TransportResponse {
+    pub status: u16,
+    // Header names are always lowercase.
+    pub headers: HashMap<String, String>,
+    pub body: Bytes,
+}
+
+impl TransportResponse {
+    pub fn ok(body: Bytes) -> Self {
+        Self {
+            status: 200,
+            headers: HashMap::new(),
+            body,
+        }
+    }
+
+    pub fn not_modified() -> Self {
+        Self {
+            status: 304,
+            headers: HashMap::new(),
+            body: Bytes::new(),
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Self {
+            status: 404,
+            headers: HashMap::new(),
+            body: Bytes::new(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers
+            .insert(name.to_ascii_lowercase(), value.to_owned());
+        self
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(|value| value.as_str())
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+// Where the grabber actually gets bytes from, so the network can be swapped out for local files or tests.
+//
+// Non-success statuses should come back as responses, errors are for when there is no response at all.
+#[async_trait]
+pub trait Transport: fmt::Debug + Send + Sync {
+    async fn fetch(&self, request: TransportRequest) -> GrabResult<TransportResponse>;
+
+    // Same as `fetch` but reporting the body as it comes in, backends that can't stream just report
+    // the whole thing once it is there.
+    async fn fetch_with_progress(
+        &self,
+        request: TransportRequest,
+        progress: &DownloadProgress,
+    ) -> GrabResult<TransportResponse> {
+        let response = self.fetch(request).await?;
+        progress.start(Some(response.body.len() as u64));
+        progress.add(response.body.len() as u64);
+        Ok(response)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+    client: Client,
+    // Shared by every request, reading the body slower than this pushes back on the connection itself.
+    bandwidth: Option<Bandwidth>,
+}
+
+impl ReqwestTransport {
+    pub fn new() -> Self {
+        Self::from_client(Client::new())
+    }
+
+    pub fn from_client(client: Client) -> Self {
+        Self {
+            client,
+            bandwidth: None,
+        }
+    }
+
+    pub fn with_bandwidth(mut self, bandwidth: Bandwidth) -> Self {
+        self.bandwidth = Some(bandwidth);
+        self
+    }
+
+    // Send requests for hosts matching a pattern through that proxy, the first match wins and anything
+    // unmatched goes direct.
+    pub fn with_proxies(proxies: &[(HostPattern, String)]) -> GrabResult<Self> {
+        let proxies = proxies
+            .iter()
+            .map(|(host, proxy)| {
+                let proxy = Url::parse(proxy)
+                    .map_err(|err| GrabError::Transport(format!("bad proxy url {:?}: {}", proxy, err)))?;
+                Ok((host.clone(), proxy))
+            })
+            .collect::<GrabResult<Vec<_>>>()?;
+
+        let proxy = Proxy::custom(move |url| {
+            let host = url.host_str()?;
+            proxies
+                .iter()
+                .find(|(pattern, _)| pattern.matches(host))
+                .map(|(_, proxy)| proxy.clone())
+        });
+
+        let client = Client::builder().proxy(proxy).build()?;
+        Ok(Self::from_client(client))
+    }
+}
+
+#[async_trait]
+impl Transport for ReqwestTransport {
+    async fn fetch(&self, request: TransportRequest) -> GrabResult<TransportResponse> {
+        self.fetch_with_progress(request, &DownloadProgress::default())
+            .await
+    }
+
+    async fn fetch_with_progress(
+        &self,
+        request: TransportRequest,
+        progress: &DownloadProgress,
+    ) -> GrabResult<TransportResponse> {
+        let mut builder = self.client.get(&request.url);
+        for (name, value) in &request.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        let mut response = builder.send().await?;
+
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| {
+                let value = value.to_str().ok()?;
+                Some((name.as_str().to_owned(), value.to_owned()))
+            })
+            .collect();
+
+        let content_length = response.content_length();
+        request.check_size(content_length.unwrap_or(0))?;
+        progress.start(content_length);
+
+        // Don't trust the header enough to allocate whatever it says up front.
+        let mut body = BytesMut::with_capacity(content_length.unwrap_or(0).min(1024 * 1024) as usize);
+        while let Some(chunk) = response.chunk().await? {
+            // Servers can lie about or leave out `Content-Length`, so keep checking.
+            request.check_size((body.len() + chunk.len()) as u64)?;
+            if let Some(bandwidth) = &self.bandwidth {
+                bandwidth.take(chunk.len() as u64).await;
+            }
+            progress.add(chunk.len() as u64);
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(TransportResponse {
+            status,
+            headers,
+            body: body.freeze(),
+        })
+    }
+}
+
+// Serves `file://` urls, plus any url prefixes that have been mounted onto a local directory tree.
+//
+// Query strings are ignored when mapping mounted urls onto files.
+#[derive(Debug, Clone, Default)]
+pub struct FileTransport {
+    mounts: Vec<(String, PathBuf)>,
+}
+
+impl FileTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Serve urls starting with `prefix` from `directory`, so `{prefix}/sets/a.json` reads `{directory}/sets/a.json`.
+    pub fn mount<P: Into<PathBuf>>(mut self, prefix: &str, directory: P) -> Self {
+        self.mounts
+            .push((prefix.trim_end_matches('/').to_owned(), directory.into()));
+        self
+    }
+
+    pub fn path_for(&self, url: &str) -> Option<PathBuf> {
+        if url.starts_with("file://") {
+            return Url::parse(url).ok()?.to_file_path().ok();
+        }
+
+        let url = strip_query(url);
+        self.mounts.iter().find_map(|(prefix, directory)| {
+            let rest = url.strip_prefix(prefix.as_str())?;
+            if !(rest.is_empty() || rest.starts_with('/')) {
+                return None;
+            }
+
+            let mut path = directory.clone();
+            for segment in rest.split('/').filter(|segment| !segment.is_empty()) {
+                let segment = percent_decode_str(segment).decode_utf8().ok()?;
+                // Don't let urls wander outside of the mounted directory.
+                if segment == ".." || segment.contains(['/', '\\']) {
+                    return None;
+                }
+                path.push(segment.as_ref());
+            }
+            Some(path)
+        })
+    }
+}
+
+// Url without any query string or fragment, for backends that don't care about resizing parameters
+// and the like.
+pub fn strip_query(url: &str) -> &str {
+    url.split(['?', '#']).next().unwrap_or(url)
+}
+
+pub fn guess_content_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "json" => Some("application/json"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "png" => Some("image/png"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl Transport for FileTransport {
+    async fn fetch(&self, request: TransportRequest) -> GrabResult<TransportResponse> {
+        let path = match self.path_for(&request.url) {
+            Some(path) => path,
+            None => return Ok(TransportResponse::not_found()),
+        };
+
+        match tokio::fs::read(&path).await {
+            Ok(body) => {
+                let mut response = TransportResponse::ok(Bytes::from(body));
+                if let Some(content_type) = guess_content_type(&path) {
+                    response = response.with_header("content-type", content_type);
+                }
+                Ok(response)
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(TransportResponse::not_found()),
+            Err(err) => Err(GrabError::Transport(format!("{:?}: {}", path, err))),
+        }
+    }
+}
+
+// Fixed set of responses held in memory, mostly for tests.
+//
+// A response inserted for a url without a query string answers for that url with any query. Conditional
+// requests matching a response's `ETag` or `Last-Modified` get a `304 Not Modified` like a real server.
+#[derive(Debug, Default)]
+pub struct MemoryTransport {
+    responses: HashMap<String, TransportResponse>,
+    // Every request that has been made, in order.
+    requests: Mutex<Vec<TransportRequest>>,
+}
+
+impl MemoryTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert<B: Into<Bytes>>(&mut self, url: &str, body: B) {
+        self.insert_response(url, TransportResponse::ok(body.into()));
+    }
+
+    pub fn insert_response(&mut self, url: &str, response: TransportResponse) {
+        self.responses.insert(url.to_owned(), response);
+    }
+
+    pub fn requests(&self) -> Vec<TransportRequest> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    pub fn requested(&self) -> Vec<String> {
+        self.requests()
+            .into_iter()
+            .map(|request| request.url)
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Transport for MemoryTransport {
+    async fn fetch(&self, request: TransportRequest) -> GrabResult<TransportResponse> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(request.clone());
+
+        let response = match self
+            .responses
+            .get(&request.url)
+            .or_else(|| self.responses.get(strip_query(&request.url)))
+        {
+            Some(response) => response,
+            None => return Ok(TransportResponse::not_found()),
+        };
+
+        let matches = |request_header: &str, response_header: &str| match (
+            request.header(request_header),
+            response.header(response_header),
+        ) {
+            (Some(wanted), Some(current)) => wanted == current,
+            _ => false,
+        };
+
+        if response.is_success() && (matches("if-none-match", "etag") || matches("if-modified-since", "last-modified")) {
+            let mut not_modified = TransportResponse::not_modified();
+            for name in &["etag", "last-modified", "cache-control"] {
+                if let Some(value) = response.header(name) {
+                    not_modified = not_modified.with_header(name, value);
+                }
+            }
+            return Ok(not_modified);
+        }
+
+        Ok(response.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        FileTransport, MemoryTransport, ReqwestTransport, Transport, TransportRequest, TransportResponse,
+    };
+    use crate::grabber::DownloadProgress;
+    use bytes::Bytes;
+    use std::path::PathBuf;
+
+    fn request(url: &str) -> TransportRequest {
+        TransportRequest::new(url)
+    }
+
+    #[test]
+    fn file_mounts() {
+        let transport = FileTransport::new().mount("https://example.com/api/", "/data");
+
+        assert_eq!(
+            transport.path_for("https://example.com/api/sets/a.json?offset=15"),
+            Some(PathBuf::from("/data/sets/a.json"))
+        );
+        assert_eq!(
+            transport.path_for("https://example.com/api/my%20sets/a%2Bb.json"),
+            Some(PathBuf::from("/data/my sets/a+b.json"))
+        );
+        assert_eq!(transport.path_for("https://example.com/api/%2E%2E/secret"), None);
+        assert_eq!(transport.path_for("https://example.com/api/a%2F..%2F..%2Fsecret"), None);
+        #[cfg(unix)]
+        {
+            assert_eq!(
+                transport.path_for("file:///tmp/a.png"),
+                Some(PathBuf::from("/tmp/a.png"))
+            );
+            assert_eq!(
+                transport.path_for("file:///tmp/my%20images/a.png"),
+                Some(PathBuf::from("/tmp/my images/a.png"))
+            );
+        }
+        #[cfg(windows)]
+        assert_eq!(
+            transport.path_for("file:///C:/images/a%20b.png"),
+            Some(PathBuf::from("C:\\images\\a b.png"))
+        );
+        assert_eq!(
+            transport.path_for("https://example.com/apiary/a.json"),
+            None
+        );
+        assert_eq!(
+            transport.path_for("https://example.com/api/../secret"),
+            None
+        );
+        assert_eq!(transport.path_for("https://elsewhere.com/api/a.json"), None);
+    }
+
+    #[tokio::test]
+    async fn serves_fixtures() {
+        let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+        let transport = FileTransport::new().mount("https://example.com", &fixtures);
+
+        let response = transport
+            .fetch(request("https://example.com/home.json"))
+            .await
+            .expect("home fixture");
+        assert!(response.is_success());
+        assert_eq!(response.header("Content-Type"), Some("application/json"));
+
+        let missing = transport
+            .fetch(request("https://example.com/nope.json"))
+            .await
+            .expect("missing fixture");
+        assert_eq!(missing.status, 404);
+    }
+
+    #[tokio::test]
+    async fn memory_records_requests() {
+        let mut transport = MemoryTransport::new();
+        transport.insert("a", &b"body"[..]);
+        transport.insert("a?width=10", &b"small"[..]);
+
+        let response = transport
+            .fetch(request("a"))
+            .await
+            .expect("memory response");
+        assert_eq!(&response.body[..], b"body");
+        assert_eq!(
+            transport.fetch(request("b")).await.expect("missing").status,
+            404
+        );
+
+        let sized = transport.fetch(request("a?width=10")).await.expect("exact query");
+        assert_eq!(&sized.body[..], b"small");
+        let resized = transport.fetch(request("a?width=20")).await.expect("any query");
+        assert_eq!(&resized.body[..], b"body");
+        assert_eq!(transport.requested().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn memory_conditional_requests() {
+        let mut transport = MemoryTransport::new();
+        transport.insert_response(
+            "a",
+            TransportResponse::ok(Bytes::from_static(b"body")).with_header("ETag", "\"v1\""),
+        );
+
+        let fresh = transport
+            .fetch(request("a").with_header("If-None-Match", "\"v1\""))
+            .await
+            .expect("matching etag");
+        assert_eq!(fresh.status, 304);
+        assert_eq!(fresh.header("etag"), Some("\"v1\""));
+
+        let changed = transport
+            .fetch(request("a").with_header("If-None-Match", "\"v0\""))
+            .await
+            .expect("old etag");
+        assert_eq!(changed.status, 200);
+        assert_eq!(transport.requests()[0].header("if-none-match"), Some("\"v1\""));
+    }
+
+    #[tokio::test]
+    async fn reqwest_streams_progress() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind local server");
+        let address = listener.local_addr().expect("local address");
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.expect("accept");
+            let mut request = [0u8; 1024];
+            let _ = socket.read(&mut request).await;
+
+            let body = vec![b'x'; 64 * 1024];
+            let header = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len());
+            socket.write_all(header.as_bytes()).await.expect("write header");
+            for chunk in body.chunks(4096) {
+                socket.write_all(chunk).await.expect("write body");
+            }
+        });
+
+        let progress = DownloadProgress::default();
+        let response = ReqwestTransport::new()
+            .fetch_with_progress(request(&format!("http://{}/image.jpg", address)), &progress)
+            .await
+            .expect("local response");
+
+        assert_eq!(response.body.len(), 64 * 1024);
+        assert_eq!(progress.total(), Some(64 * 1024));
+        assert_eq!(progress.received(), 64 * 1024);
+        assert_eq!(progress.fraction(), Some(1.0));
+    }
+
+    #[tokio::test]
+    async fn reqwest_stops_at_max_bytes() {
+        use crate::grabber::GrabError;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind local server");
+        let address = listener.local_addr().expect("local address");
+
+        // No `Content-Length`, so the only way to notice is while reading.
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.expect("accept");
+            let mut request = [0u8; 1024];
+            let _ = socket.read(&mut request).await;
+
+            let _ = socket.write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n").await;
+            for _ in 0..1024 {
+                if socket.write_all(&[b'x'; 1024]).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let response = ReqwestTransport::new()
+            .fetch(request(&format!("http://{}/bomb.png", address)).with_max_bytes(16 * 1024))
+            .await;
+        assert_eq!(response.map(|_| ()), Err(GrabError::ResponseTooLarge { limit: 16 * 1024 }));
+    }
+
+    #[test]
+    fn size_limits() {
+        use crate::grabber::GrabError;
+
+        assert!(request("a").check_size(u64::MAX).is_ok());
+        let limited = request("a").with_max_bytes(1024);
+        assert!(limited.check_size(1024).is_ok());
+        assert_eq!(limited.check_size(1025), Err(GrabError::ResponseTooLarge { limit: 1024 }));
+    }
+
+    #[tokio::test]
+    async fn reqwest_proxies_matching_hosts() {
+        use crate::grabber::HostPattern;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind local proxy");
+        let address = listener.local_addr().expect("local address");
+
+        let proxied = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.expect("accept");
+            let mut request = [0u8; 1024];
+            let read = socket.read(&mut request).await.expect("read request");
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 7\r\nConnection: close\r\n\r\nproxied")
+                .await
+                .expect("write response");
+            String::from_utf8_lossy(&request[..read]).into_owned()
+        });
+
+        let transport = ReqwestTransport::with_proxies(&[(
+            HostPattern::parse("*.example.invalid"),
+            format!("http://{}", address),
+        )])
+        .expect("proxy transport");
+        let response = transport
+            .fetch(request("http://api.example.invalid/home.json"))
+            .await
+            .expect("proxied response");
+        assert_eq!(&response.body[..], b"proxied");
+
+        // Proxies get sent the full url rather than just the path.
+        let sent = proxied.await.expect("proxy task");
+        assert!(sent.starts_with("GET http://api.example.invalid/home.json HTTP/1.1"), "{}", sent);
+
+        assert!(ReqwestTransport::with_proxies(&[(HostPattern::Any, "not a url".to_owned())]).is_err());
+    }
+
+    #[tokio::test]
+    async fn progress_without_streaming() {
+        let mut transport = MemoryTransport::new();
+        transport.insert("a", &b"body"[..]);
+
+        let progress = DownloadProgress::default();
+        transport
+            .fetch_with_progress(request("a"), &progress)
+            .await
+            .expect("memory response");
+        assert_eq!(progress.fraction(), Some(1.0));
+    }
+}
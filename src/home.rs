@@ -1,54 +1,167 @@
+use serde::de::{self, Deserialize, DeserializeOwned, Deserializer};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_json::Value;
 use uuid::Uuid;
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fmt;
+use std::marker::PhantomData;
 
 // Leaving a good portion of the unused fields commented out for visibility
 // but don't deserialize into them since I don't know the real schema and don't
 // want this all to fail because something is sometimes not sent, or is an enum, etc.
+//
+// Item metadata is modelled in full, but every field we don't strictly need is read leniently so a
+// surprise in any of them just leaves that field empty instead of failing the whole home page.
+//
+// Anything not modelled ends up in each struct's `extra`, and so does anything we couldn't read or that
+// only says what the field's default would, like `null` or `[]`. Fields are only written back out when
+// they hold something else, so re-serialising gives back the same json.
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+// Fields we don't model, by their json name.
+pub type Extra = serde_json::Map<String, Value>;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Home {
     pub data: HomeKind,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RefSet {
     pub data: SetKind,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum SetKind {
     CuratedSet(Set),
     PersonalizedCuratedSet(Set),
     TrendingSet(Set),
+    // Anything added to the API since, kept as it was sent.
+    Unknown { kind: String, raw: Value },
 }
 
 impl SetKind {
-    pub fn set(&self) -> &Set {
+    // Unknown kinds are read as a plain set if they look enough like one, `None` if they don't.
+    pub fn set(&self) -> Option<Cow<'_, Set>> {
+        match self {
+            SetKind::CuratedSet(set) => Some(Cow::Borrowed(set)),
+            SetKind::PersonalizedCuratedSet(set) => Some(Cow::Borrowed(set)),
+            SetKind::TrendingSet(set) => Some(Cow::Borrowed(set)),
+            SetKind::Unknown { raw, .. } => serde_json::from_value(raw.clone()).ok().map(Cow::Owned),
+        }
+    }
+
+    pub fn kind(&self) -> &str {
         match self {
-            SetKind::CuratedSet(set) => set,
-            SetKind::PersonalizedCuratedSet(set) => set,
-            SetKind::TrendingSet(set) => set,
+            SetKind::CuratedSet(_) => "CuratedSet",
+            SetKind::PersonalizedCuratedSet(_) => "PersonalizedCuratedSet",
+            SetKind::TrendingSet(_) => "TrendingSet",
+            SetKind::Unknown { kind, .. } => kind,
         }
     }
+
+    pub fn is_unknown(&self) -> bool {
+        matches!(self, SetKind::Unknown { .. })
+    }
+}
+
+impl<'de> Deserialize<'de> for SetKind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (kind, raw) = split_kind(deserializer)?;
+        Ok(match kind.as_str() {
+            "CuratedSet" => SetKind::CuratedSet(from_raw(raw)?),
+            "PersonalizedCuratedSet" => SetKind::PersonalizedCuratedSet(from_raw(raw)?),
+            "TrendingSet" => SetKind::TrendingSet(from_raw(raw)?),
+            _ => SetKind::Unknown { kind, raw },
+        })
+    }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+impl Serialize for SetKind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        match self {
+            SetKind::CuratedSet(set) | SetKind::PersonalizedCuratedSet(set) | SetKind::TrendingSet(set) => {
+                map.serialize_entry(self.kind(), set)?
+            }
+            SetKind::Unknown { kind, raw } => map.serialize_entry(kind, raw)?,
+        }
+        map.end()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum HomeKind {
     StandardCollection(Collection),
+    // Anything added to the API since, kept as it was sent.
+    Unknown { kind: String, raw: Value },
 }
 
 impl HomeKind {
-    pub fn collection(&self) -> &Collection {
+    // Unknown kinds are read as a plain collection if they look enough like one, `None` if they don't.
+    pub fn collection(&self) -> Option<Cow<'_, Collection>> {
+        match self {
+            HomeKind::StandardCollection(collection) => Some(Cow::Borrowed(collection)),
+            HomeKind::Unknown { raw, .. } => serde_json::from_value(raw.clone()).ok().map(Cow::Owned),
+        }
+    }
+
+    pub fn kind(&self) -> &str {
         match self {
-            HomeKind::StandardCollection(collection) => collection,
+            HomeKind::StandardCollection(_) => "StandardCollection",
+            HomeKind::Unknown { kind, .. } => kind,
         }
     }
+
+    pub fn is_unknown(&self) -> bool {
+        matches!(self, HomeKind::Unknown { .. })
+    }
+}
+
+impl<'de> Deserialize<'de> for HomeKind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (kind, raw) = split_kind(deserializer)?;
+        Ok(match kind.as_str() {
+            "StandardCollection" => HomeKind::StandardCollection(from_raw(raw)?),
+            _ => HomeKind::Unknown { kind, raw },
+        })
+    }
+}
+
+impl Serialize for HomeKind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        match self {
+            HomeKind::StandardCollection(collection) => map.serialize_entry(self.kind(), collection)?,
+            HomeKind::Unknown { kind, raw } => map.serialize_entry(kind, raw)?,
+        }
+        map.end()
+    }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+// Kinds are sent externally tagged, `{ "CuratedSet": { .. } }`.
+fn split_kind<'de, D: Deserializer<'de>>(deserializer: D) -> Result<(String, Value), D::Error> {
+    let mut entries = serde_json::Map::deserialize(deserializer)?.into_iter();
+    match (entries.next(), entries.next()) {
+        (Some((kind, raw)), None) => Ok((kind, raw)),
+        _ => Err(de::Error::custom("expected an object with exactly one kind")),
+    }
+}
+
+// Kinds we do know about still have to parse properly.
+fn from_raw<T: DeserializeOwned, E: de::Error>(raw: Value) -> Result<T, E> {
+    serde_json::from_value(raw).map_err(E::custom)
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Collection {
     //pub call_to_action: ?,
@@ -59,241 +172,898 @@ pub struct Collection {
     //pub text: Text,
     //pub video_art: Vec<Image>,
     //pub type: String,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Container {
     pub set: Set,
     //pub type: String,
     pub style: String,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Set {
     //pub content_class: String,
     pub items: Option<Vec<Item>>,
-    //pub meta: Option<Meta>,
+    // Which page of the set `items` is, missing for set refs.
+    pub meta: Option<Meta>,
+    pub set_id: Option<Uuid>,
     //pub type: String,
     //pub style: Option<String>,
     pub ref_id: Option<Uuid>,
     //pub ref_id_type: String,
     //pub ref_type: String,
     pub text: TextRefs,
+    pub extra: Extra,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
+impl<'de> Deserialize<'de> for Set {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut fields = Fields::new(deserializer)?;
+        Ok(Set {
+            // A bad item is dropped rather than taking the set, and the whole page with it, down too.
+            items: fields.lenient_list("items"),
+            meta: fields.lenient("meta"),
+            set_id: fields.lenient("setId"),
+            ref_id: fields.optional("refId")?,
+            text: fields.required("text")?,
+            extra: fields.into_extra(),
+        })
+    }
+}
+
+impl Serialize for Set {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut fields = FieldsOut::new(serializer.serialize_map(None)?, &self.extra);
+        fields.optional("items", &self.items)?;
+        fields.optional("meta", &self.meta)?;
+        fields.optional("setId", &self.set_id)?;
+        fields.optional("refId", &self.ref_id)?;
+        fields.required("text", &self.text)?;
+        fields.end()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Item {
-    //call_to_action: ?,
-    //content_id: Uuid,
-    //current_availability: Availability,
-    //encoded_series_id: String,
+    // Always null so far, so no idea what shape it is.
+    pub call_to_action: Option<Value>,
+    pub content_id: Option<Uuid>,
+    pub current_availability: Option<Availability>,
+    pub encoded_series_id: Option<String>,
+    // Items without any images still parse, they just have nothing to show.
     pub image: ImageRefs,
-    //series_id: Uuid,
+    pub series_id: Option<Uuid>,
+    // Only on videos, `movie`, `episode`, etc.
+    pub program_type: Option<String>,
     pub text: TextRefs,
-    //text_experience_id: Uuid,
-    //tags: Vec<Tag>,
-    //media_rights: MediaRights,
-    //ratings: Vec<Rating>,
-    //releases: Vec<Release>,
-    //type: String,
-    //video_art: Vec<VideoArt>,
+    pub text_experience_id: Option<Uuid>,
+    pub tags: Vec<Tag>,
+    pub media_rights: Option<MediaRights>,
+    pub ratings: Vec<Rating>,
+    pub releases: Vec<Release>,
+    // `DmcSeries`, `DmcVideo`, etc, sent as `type`.
+    pub kind: Option<String>,
+    pub video_art: Vec<VideoArt>,
+    pub extra: Extra,
+}
+
+impl<'de> Deserialize<'de> for Item {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut fields = Fields::new(deserializer)?;
+        Ok(Item {
+            call_to_action: fields.optional("callToAction")?,
+            content_id: fields.lenient("contentId"),
+            current_availability: fields.lenient("currentAvailability"),
+            encoded_series_id: fields.lenient("encodedSeriesId"),
+            image: fields.lenient("image"),
+            series_id: fields.lenient("seriesId"),
+            program_type: fields.lenient("programType"),
+            text: fields.required("text")?,
+            text_experience_id: fields.lenient("textExperienceId"),
+            tags: fields.lenient_vec("tags"),
+            media_rights: fields.lenient("mediaRights"),
+            ratings: fields.lenient_vec("ratings"),
+            releases: fields.lenient_vec("releases"),
+            kind: fields.lenient("type"),
+            video_art: fields.lenient_vec("videoArt"),
+            extra: fields.into_extra(),
+        })
+    }
+}
+
+impl Serialize for Item {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut fields = FieldsOut::new(serializer.serialize_map(None)?, &self.extra);
+        fields.optional("callToAction", &self.call_to_action)?;
+        fields.optional("contentId", &self.content_id)?;
+        fields.optional("currentAvailability", &self.current_availability)?;
+        fields.optional("encodedSeriesId", &self.encoded_series_id)?;
+        fields.optional("image", &self.image)?;
+        fields.optional("seriesId", &self.series_id)?;
+        fields.optional("programType", &self.program_type)?;
+        fields.required("text", &self.text)?;
+        fields.optional("textExperienceId", &self.text_experience_id)?;
+        fields.optional("tags", &self.tags)?;
+        fields.optional("mediaRights", &self.media_rights)?;
+        fields.optional("ratings", &self.ratings)?;
+        fields.optional("releases", &self.releases)?;
+        fields.optional("type", &self.kind)?;
+        fields.optional("videoArt", &self.video_art)?;
+        fields.end()
+    }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+impl Item {
+    pub fn title(&self) -> &str {
+        &self.text.title.full.details().content
+    }
+
+    pub fn release_year(&self) -> Option<u16> {
+        self.releases.iter().find_map(Release::year)
+    }
+
+    pub fn is_series(&self) -> bool {
+        self.series_id.is_some() || self.kind.as_deref() == Some("DmcSeries")
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ImageRefs {
     // Map of aspect ratios to details of image specifics.
+    #[serde(default)]
     pub tile: HashMap<String, Image>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Image {
-    Default { default: ImageDetails },
-    Series { default: ImageDetails },
-    Program { default: ImageDetails },
+    Default { default: ImageDetails, extra: Extra },
+    Series { default: ImageDetails, extra: Extra },
+    Program { default: ImageDetails, extra: Extra },
+}
+
+impl<'de> Deserialize<'de> for Image {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (kind, raw) = split_kind(deserializer)?;
+        let mut fields = Fields::from_raw(raw)?;
+        let default = fields.required("default")?;
+        let extra = fields.into_extra();
+        Ok(match kind.as_str() {
+            "default" => Image::Default { default, extra },
+            "series" => Image::Series { default, extra },
+            "program" => Image::Program { default, extra },
+            _ => return Err(de::Error::unknown_variant(&kind, &["default", "series", "program"])),
+        })
+    }
+}
+
+impl Serialize for Image {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // What's under the kind, `{ "default": { .. }, .. }`.
+        struct Variant<'a>(&'a Image);
+
+        impl Serialize for Variant<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut fields = FieldsOut::new(serializer.serialize_map(None)?, self.0.extra());
+                fields.required("default", self.0.details())?;
+                fields.end()
+            }
+        }
+
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(self.kind(), &Variant(self))?;
+        map.end()
+    }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TextRefs {
     pub title: Title,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Title {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub slug: Option<Text>,
     pub full: Text,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Text {
-    Set { default: TextDetails },
-    Collection { default: TextDetails },
-    Program { default: TextDetails },
-    Series { default: TextDetails },
+    Set(LocalizedText),
+    Collection(LocalizedText),
+    Program(LocalizedText),
+    Series(LocalizedText),
+    // Anything added to the API since, kept as it was sent. Shown as localized text if it looks enough
+    // like it, otherwise as nothing at all.
+    Unknown { kind: String, raw: Value, text: LocalizedText },
 }
 
 impl Text {
+    pub fn text(&self) -> &LocalizedText {
+        match self {
+            Text::Set(text) => text,
+            Text::Collection(text) => text,
+            Text::Program(text) => text,
+            Text::Series(text) => text,
+            Text::Unknown { text, .. } => text,
+        }
+    }
+
+    pub fn kind(&self) -> &str {
+        match self {
+            Text::Set(_) => "set",
+            Text::Collection(_) => "collection",
+            Text::Program(_) => "program",
+            Text::Series(_) => "series",
+            Text::Unknown { kind, .. } => kind,
+        }
+    }
+
     pub fn details(&self) -> &TextDetails {
+        &self.text().default
+    }
+
+    pub fn localized(&self, languages: &Languages) -> &TextDetails {
+        self.text().localized(languages)
+    }
+}
+
+impl<'de> Deserialize<'de> for Text {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (kind, raw) = split_kind(deserializer)?;
+        Ok(match kind.as_str() {
+            "set" => Text::Set(from_raw(raw)?),
+            "collection" => Text::Collection(from_raw(raw)?),
+            "program" => Text::Program(from_raw(raw)?),
+            "series" => Text::Series(from_raw(raw)?),
+            _ => {
+                let text = LocalizedText::deserialize(&raw).unwrap_or_default();
+                Text::Unknown { kind, raw, text }
+            }
+        })
+    }
+}
+
+impl Serialize for Text {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
         match self {
-            Text::Set { default } => default,
-            Text::Collection { default } => default,
-            Text::Program { default } => default,
-            Text::Series { default } => default,
+            Text::Unknown { kind, raw, .. } => map.serialize_entry(kind, raw)?,
+            _ => map.serialize_entry(self.kind(), self.text())?,
+        }
+        map.end()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LocalizedText {
+    pub default: TextDetails,
+    // Any other languages sent alongside the default. What they are keyed by isn't something to rely
+    // on, it's the `language` inside each that gets matched.
+    pub localized: BTreeMap<String, TextDetails>,
+    // Entries that aren't text we can read, kept as they came.
+    pub extra: Extra,
+}
+
+impl<'de> Deserialize<'de> for LocalizedText {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut fields = Fields::new(deserializer)?;
+        let default = fields.required("default")?;
+
+        let mut localized = BTreeMap::new();
+        let mut extra = Extra::new();
+        for (key, value) in fields.into_extra() {
+            if let Ok(details) = TextDetails::deserialize(&value) {
+                localized.insert(key, details);
+            } else {
+                extra.insert(key, value);
+            }
+        }
+
+        Ok(LocalizedText { default, localized, extra })
+    }
+}
+
+impl Serialize for LocalizedText {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut fields = FieldsOut::new(serializer.serialize_map(None)?, &self.extra);
+        fields.required("default", &self.default)?;
+        for (key, details) in &self.localized {
+            fields.required(key, details)?;
+        }
+        fields.end()
+    }
+}
+
+impl LocalizedText {
+    // Best match for the most preferred language we have, otherwise the default.
+    pub fn localized(&self, languages: &Languages) -> &TextDetails {
+        std::iter::once(&self.default)
+            .chain(self.localized.values())
+            .filter_map(|details| Some((languages.rank(&details.language)?, details)))
+            .min_by_key(|(rank, _)| *rank)
+            .map_or(&self.default, |(_, details)| details)
+    }
+}
+
+// Languages to show text in, most preferred first, as tags like `fr-CA` or `en`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Languages {
+    preferred: Vec<String>,
+}
+
+impl Languages {
+    pub fn new<S: AsRef<str>>(preferred: &[S]) -> Self {
+        Self {
+            preferred: preferred
+                .iter()
+                .map(|language| language.as_ref().trim().to_ascii_lowercase())
+                .filter(|language| !language.is_empty())
+                .collect(),
         }
     }
+
+    // Comma separated, `fr-CA,fr,en`.
+    pub fn parse(list: &str) -> Self {
+        Self::new(&list.split(',').collect::<Vec<_>>())
+    }
+
+    pub fn preferred(&self) -> &[String] {
+        &self.preferred
+    }
+
+    // Moves the most preferred language to the back, to flip between them.
+    pub fn rotate(&mut self) {
+        if !self.preferred.is_empty() {
+            self.preferred.rotate_left(1);
+        }
+    }
+
+    // Lower is better, `None` when `language` isn't wanted at all. For each preference an exact match
+    // beats the plain language, which beats some other region of it, so `fr-BE` would rather have `fr`
+    // than `fr-CA` but takes either.
+    fn rank(&self, language: &str) -> Option<usize> {
+        let language = language.to_ascii_lowercase();
+        let primary = |tag: &str| tag.split(['-', '_']).next().unwrap_or("").to_owned();
+
+        self.preferred.iter().enumerate().find_map(|(index, preferred)| {
+            if *preferred == language {
+                Some(index * 3)
+            } else if primary(preferred) == language {
+                Some(index * 3 + 1)
+            } else if primary(preferred) == primary(&language) {
+                Some(index * 3 + 2)
+            } else {
+                None
+            }
+        })
+    }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TextDetails {
     pub content: String,
     pub language: String,
     //sourceEntity: String,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+impl ImageRefs {
+    // Aspect ratios there are tile images for, in no particular order.
+    pub fn tile_ratios(&self) -> impl Iterator<Item = f32> + '_ {
+        self.tile.keys().filter_map(|key| key.parse().ok())
+    }
+
+    // Tile image for the first of `ratios` there is one for, along with which ratio that was.
+    //
+    // Keys are compared as numbers so `1.0` and `1.00` are the same ratio.
+    pub fn tile_for(&self, ratios: &[f32]) -> Option<(f32, &Image)> {
+        ratios.iter().find_map(|&ratio| {
+            self.tile
+                .iter()
+                .find(|(key, _)| key.parse::<f32>().map_or(false, |key| same_ratio(key, ratio)))
+                .map(|(_, image)| (ratio, image))
+        })
+    }
+
+    // Tile image in whatever shape is closest to `ratio`, for when there isn't one in it exactly.
+    pub fn closest_tile(&self, ratio: f32) -> Option<(f32, &Image)> {
+        // Compared as how many times wider or narrower, so 0.71 is as far from 1.0 as 1.41 is.
+        let distance = |other: f32| (other / ratio).ln().abs();
+        let closest = self
+            .tile_ratios()
+            .filter(|other| *other > 0.0)
+            .min_by(|a, b| distance(*a).partial_cmp(&distance(*b)).unwrap_or(std::cmp::Ordering::Equal))?;
+        self.tile_for(&[closest])
+    }
+}
+
+// Ratios only ever come with two decimal places.
+pub fn same_ratio(a: f32, b: f32) -> bool {
+    (a - b).abs() < 0.005
 }
 
 impl Image {
     pub fn details(&self) -> &ImageDetails {
         match self {
-            Image::Default { default } => default,
-            Image::Series { default } => default,
-            Image::Program { default } => default,
+            Image::Default { default, .. } => default,
+            Image::Series { default, .. } => default,
+            Image::Program { default, .. } => default,
+        }
+    }
+
+    pub fn kind(&self) -> &str {
+        match self {
+            Image::Default { .. } => "default",
+            Image::Series { .. } => "series",
+            Image::Program { .. } => "program",
+        }
+    }
+
+    // Anything sent alongside `default` that we don't model.
+    pub fn extra(&self) -> &Extra {
+        match self {
+            Image::Default { extra, .. } => extra,
+            Image::Series { extra, .. } => extra,
+            Image::Program { extra, .. } => extra,
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ImageDetails {
     //master_id: Uuid, // not actually a uuid, probably just a string?
     pub master_width: u32,
     pub master_height: u32,
     pub url: String, // Seems to be resizable based on url encoded parameters.
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+impl ImageDetails {
+    // Same image scaled by the api to `width` pixels wide, the height follows the aspect ratio.
+    pub fn sized_url(&self, width: u32) -> String {
+        let mut url = match reqwest::Url::parse(&self.url) {
+            Ok(url) => url,
+            Err(_) => return self.url.clone(),
+        };
+
+        let query = url
+            .query_pairs()
+            .filter(|(name, _)| name != "width")
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect::<Vec<_>>();
+        url.query_pairs_mut()
+            .clear()
+            .extend_pairs(query)
+            .append_pair("width", &width.to_string());
+
+        url.into()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct VideoArt {
     pub media_metadata: MediaMetadata,
     pub purpose: String,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MediaMetadata {
     pub urls: Vec<Url>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Url {
     pub url: String,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Release {
-    pub release_date: String,
-    pub release_type: String,
-    pub release_year: u16,
-    //territory: Option<?>,
+    // `2020-12-25`, null for most series.
+    pub release_date: Option<String>,
+    pub release_type: Option<String>,
+    pub release_year: Option<StringOrNumber>,
+    pub territory: Option<Value>,
+    pub extra: Extra,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
+impl<'de> Deserialize<'de> for Release {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut fields = Fields::new(deserializer)?;
+        Ok(Release {
+            release_date: fields.optional("releaseDate")?,
+            release_type: fields.optional("releaseType")?,
+            release_year: fields.optional("releaseYear")?,
+            territory: fields.optional("territory")?,
+            extra: fields.into_extra(),
+        })
+    }
+}
+
+impl Serialize for Release {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut fields = FieldsOut::new(serializer.serialize_map(None)?, &self.extra);
+        fields.optional("releaseDate", &self.release_date)?;
+        fields.optional("releaseType", &self.release_type)?;
+        fields.optional("releaseYear", &self.release_year)?;
+        fields.optional("territory", &self.territory)?;
+        fields.end()
+    }
+}
+
+impl Release {
+    pub fn year(&self) -> Option<u16> {
+        let year = self.release_year.as_ref()?.as_u64()?;
+        u16::try_from(year).ok()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Rating {
-    //advisories: Vec<?>,
+    pub advisories: Vec<Value>,
     pub description: Option<String>,
-    pub system: String,
-    pub value: String,
+    // `TVPG`, `MPAA`, etc.
+    pub system: Option<String>,
+    // `TV-PG`, `PG-13`, but some systems are just a number.
+    pub value: StringOrNumber,
+    pub extra: Extra,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
+impl<'de> Deserialize<'de> for Rating {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut fields = Fields::new(deserializer)?;
+        Ok(Rating {
+            advisories: fields.optional("advisories")?,
+            description: fields.optional("description")?,
+            system: fields.optional("system")?,
+            value: fields.required("value")?,
+            extra: fields.into_extra(),
+        })
+    }
+}
+
+impl Serialize for Rating {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut fields = FieldsOut::new(serializer.serialize_map(None)?, &self.extra);
+        fields.optional("advisories", &self.advisories)?;
+        fields.optional("description", &self.description)?;
+        fields.optional("system", &self.system)?;
+        fields.required("value", &self.value)?;
+        fields.end()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct MediaRights {
-    //pub download_blocked: bool,
-//pub pcon_blocked: bool,
+    pub download_blocked: Option<bool>,
+    pub pcon_blocked: Option<bool>,
+    pub extra: Extra,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
+impl<'de> Deserialize<'de> for MediaRights {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut fields = Fields::new(deserializer)?;
+        Ok(MediaRights {
+            download_blocked: fields.optional("downloadBlocked")?,
+            pcon_blocked: fields.optional("pconBlocked")?,
+            extra: fields.into_extra(),
+        })
+    }
+}
+
+impl Serialize for MediaRights {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut fields = FieldsOut::new(serializer.serialize_map(None)?, &self.extra);
+        fields.optional("downloadBlocked", &self.download_blocked)?;
+        fields.optional("pconBlocked", &self.pcon_blocked)?;
+        fields.end()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Tag {
     pub display_name: Option<String>,
-    //pub type: String,
-    pub value: String,
+    // Sent as `type`.
+    pub kind: Option<String>,
+    pub value: StringOrNumber,
+    pub extra: Extra,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
+impl<'de> Deserialize<'de> for Tag {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut fields = Fields::new(deserializer)?;
+        Ok(Tag {
+            display_name: fields.optional("displayName")?,
+            kind: fields.optional("type")?,
+            value: fields.required("value")?,
+            extra: fields.into_extra(),
+        })
+    }
+}
+
+impl Serialize for Tag {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut fields = FieldsOut::new(serializer.serialize_map(None)?, &self.extra);
+        fields.optional("displayName", &self.display_name)?;
+        fields.optional("type", &self.kind)?;
+        fields.required("value", &self.value)?;
+        fields.end()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Availability {
-    pub region: String,
-    //pub kids_mode: bool,
+    pub region: Option<String>,
+    pub kids_mode: Option<bool>,
+    pub extra: Extra,
+}
+
+impl<'de> Deserialize<'de> for Availability {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut fields = Fields::new(deserializer)?;
+        Ok(Availability {
+            region: fields.optional("region")?,
+            kids_mode: fields.optional("kidsMode")?,
+            extra: fields.into_extra(),
+        })
+    }
+}
+
+impl Serialize for Availability {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut fields = FieldsOut::new(serializer.serialize_map(None)?, &self.extra);
+        fields.optional("region", &self.region)?;
+        fields.optional("kidsMode", &self.kids_mode)?;
+        fields.end()
+    }
+}
+
+// Some fields come through as a string one time and a number the next.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum StringOrNumber {
+    Number(serde_json::Number),
+    String(String),
+}
+
+impl StringOrNumber {
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            StringOrNumber::Number(number) => number.as_u64(),
+            StringOrNumber::String(string) => string.trim().parse().ok(),
+        }
+    }
+}
+
+impl fmt::Display for StringOrNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StringOrNumber::Number(number) => write!(f, "{}", number),
+            StringOrNumber::String(string) => f.write_str(string),
+        }
+    }
+}
+
+// Object a struct is read out of field by field, whatever is still in it at the end is the struct's
+// `extra`.
+//
+// A field only leaves the object once it reads as something other than its default, so anything we
+// couldn't read, and values like `null` or `[]`, stay behind to be written back out as they came.
+struct Fields<E> {
+    values: Extra,
+    error: PhantomData<E>,
+}
+
+impl<E: de::Error> Fields<E> {
+    fn new<'de, D: Deserializer<'de, Error = E>>(deserializer: D) -> Result<Self, E> {
+        Ok(Fields {
+            values: Extra::deserialize(deserializer)?,
+            error: PhantomData,
+        })
+    }
+
+    fn from_raw(raw: Value) -> Result<Self, E> {
+        Ok(Fields {
+            values: from_raw(raw)?,
+            error: PhantomData,
+        })
+    }
+
+    fn required<T: DeserializeOwned>(&mut self, name: &'static str) -> Result<T, E> {
+        let value = self.values.remove(name).ok_or_else(|| E::missing_field(name))?;
+        from_raw(value)
+    }
+
+    // Can be left out, but has to read if it isn't.
+    fn optional<T: DeserializeOwned + Default + PartialEq>(&mut self, name: &str) -> Result<T, E> {
+        let value = match self.values.get(name) {
+            Some(value) => T::deserialize(value).map_err(E::custom)?,
+            None => return Ok(T::default()),
+        };
+        Ok(self.take(name, value))
+    }
+
+    // The default instead of an error when the field is there but not the shape we expect.
+    fn lenient<T: DeserializeOwned + Default + PartialEq>(&mut self, name: &str) -> T {
+        match self.values.get(name).map(T::deserialize) {
+            Some(Ok(value)) => self.take(name, value),
+            _ => T::default(),
+        }
+    }
+
+    // Drops any elements that aren't the shape we expect rather than the whole list, which is then kept
+    // as it came.
+    fn lenient_vec<T: DeserializeOwned>(&mut self, name: &str) -> Vec<T> {
+        let values = match self.values.get(name) {
+            Some(Value::Array(values)) => values,
+            _ => return Vec::new(),
+        };
+
+        let mut read = Vec::with_capacity(values.len());
+        for value in values {
+            match T::deserialize(value) {
+                Ok(value) => read.push(value),
+                Err(err) => log::warn!("dropping one of `{}` that doesn't parse: {}", name, err),
+            }
+        }
+        if !read.is_empty() && read.len() == values.len() {
+            self.values.remove(name);
+        }
+        read
+    }
+
+    // Same as `lenient_vec`, but `None` rather than empty when there isn't a list at all.
+    fn lenient_list<T: DeserializeOwned>(&mut self, name: &str) -> Option<Vec<T>> {
+        match self.values.get(name) {
+            Some(Value::Array(_)) => Some(self.lenient_vec(name)),
+            _ => None,
+        }
+    }
+
+    fn take<T: Default + PartialEq>(&mut self, name: &str, value: T) -> T {
+        if value != T::default() {
+            self.values.remove(name);
+        }
+        value
+    }
+
+    fn into_extra(self) -> Extra {
+        self.values
+    }
+}
+
+// Writes a struct back out as the object it was read from. Fields left at their default, or that are
+// still in `extra` as they came, are skipped in favour of whatever `extra` has.
+struct FieldsOut<'a, M> {
+    map: M,
+    extra: &'a Extra,
+}
+
+impl<'a, M: SerializeMap> FieldsOut<'a, M> {
+    fn new(map: M, extra: &'a Extra) -> Self {
+        FieldsOut { map, extra }
+    }
+
+    fn required<T: Serialize>(&mut self, name: &str, value: &T) -> Result<(), M::Error> {
+        self.map.serialize_entry(name, value)
+    }
+
+    fn optional<T: Serialize + Default + PartialEq>(&mut self, name: &str, value: &T) -> Result<(), M::Error> {
+        if *value == T::default() || self.extra.contains_key(name) {
+            return Ok(());
+        }
+        self.map.serialize_entry(name, value)
+    }
+
+    fn end(mut self) -> Result<M::Ok, M::Error> {
+        for (name, value) in self.extra {
+            self.map.serialize_entry(name, value)?;
+        }
+        self.map.end()
+    }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+// Unlike everything else this comes through in snake case.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Meta {
+    // Items in the whole set, not just this page.
     pub hits: u32,
     pub offset: u32,
     pub page_size: u32,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+impl Meta {
+    // Offset of the page after this one, `None` once this page reaches the end of the set.
+    pub fn next_offset(&self) -> Option<u32> {
+        if self.page_size == 0 {
+            return None;
+        }
+
+        let next = self.offset.saturating_add(self.page_size);
+        if next < self.hits {
+            Some(next)
+        } else {
+            None
+        }
+    }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CollectionGroup {
     pub collection_group_id: Uuid,
     //pub content_class: String,
     //pub key: String,
     //pub slugs: Vec<Slug>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 // Non human friendly data?
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Slug {
     pub language: String,
     pub value: String,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 #[cfg(test)]
 mod test {
-    use super::{Home, RefSet};
-
-    fn fetch_home() -> Home {
-        let url = "https://cd-static.bamgrid.com/dp-117731241344/home.json";
-        reqwest::blocking::get(url)
-            .expect("response from url")
-            .json::<Home>()
-            .expect("working home deserialization")
-    }
+    use super::{Home, Image, Item, Languages, Meta, RefSet, Set, Text};
+    use crate::fixtures;
 
-    // Can we fetch and deserialize the home screen.
+    // Can we deserialize the home screen.
     #[test]
     fn deserialize_home() {
-        fetch_home();
+        fixtures::home();
     }
 
     #[test]
     fn deserialize_refset() {
-        let url = "https://cd-static.bamgrid.com/dp-117731241344/sets/bd1bfb9a-bbf7-43a0-ac5e-3e3889d7224d.json";
-        reqwest::blocking::get(url)
-            .expect("response from url")
-            .json::<RefSet>()
-            .expect("working refset deserialization");
+        let bytes = fixtures::fixture_bytes("sets/bd1bfb9a-bbf7-43a0-ac5e-3e3889d7224d.json");
+        serde_json::from_slice::<RefSet>(&bytes).expect("working refset deserialization");
     }
 
-    // Can fetch the home screen and load an image correctly. Somewhat redundant with deserialization.
+    // Can grab an image reference from the home screen and load an image in that format.
     #[test]
     fn fetch_png() {
         use crate::image::EncodableLayout;
-        let home = fetch_home();
+        let home = fixtures::home();
 
-        let items = home.data.collection().containers[0]
+        let collection = home.data.collection().expect("collection");
+        let items = collection.containers[0]
             .set
             .items
             .as_ref()
@@ -308,24 +1078,291 @@ mod test {
 
         let image_details = image.details();
         println!("{:?}", image_details);
-        let bytes = reqwest::blocking::get(&image_details.url)
-            .expect("response from url")
-            .bytes()
-            .expect("expected jpeg bytes");
+        assert!(image_details.master_width > 0 && image_details.master_height > 0);
 
+        let bytes = fixtures::png_bytes();
         let _img = image::load_from_memory(bytes.as_bytes()).expect("load image from response");
     }
 
+    #[test]
+    fn sized_image_url() {
+        let home = fixtures::home();
+        let collection = home.data.collection().expect("collection");
+        let items = collection.containers[0]
+            .set
+            .items
+            .as_ref()
+            .expect("expected items");
+        let details = items[0].image.tile["1.78"].details();
+
+        assert!(details.url.ends_with("&width=500"));
+        let sized = details.sized_url(384);
+        assert_eq!(sized, details.url.replace("width=500", "width=384"));
+    }
+
+    #[test]
+    fn tile_aspect_ratios() {
+        let home = fixtures::home();
+        let collection = home.data.collection().expect("collection");
+        let items = collection.containers[0]
+            .set
+            .items
+            .as_ref()
+            .expect("expected items");
+
+        let (ratio, image) = items[0].image.tile_for(&[1.33, 1.78, 0.71]).expect("tile");
+        assert_eq!(ratio, 1.78);
+        assert_eq!(image, &items[0].image.tile["1.78"]);
+        // Luca only has posters.
+        assert_eq!(items[2].image.tile_for(&[1.78, 0.71]).map(|(ratio, _)| ratio), Some(0.71));
+        assert!(items[2].image.tile_for(&[1.78]).is_none());
+
+        let mut square = items[2].image.clone();
+        let poster = square.tile.remove("0.71").expect("poster");
+        square.tile.insert("1.00".to_owned(), poster);
+        assert_eq!(square.tile_for(&[1.0]).map(|(ratio, _)| ratio), Some(1.0));
+        assert_eq!(square.tile_ratios().collect::<Vec<_>>(), vec![1.0]);
+    }
+
+    #[test]
+    fn full_item_metadata() {
+        let home = fixtures::home();
+        let collection = home.data.collection().expect("collection");
+        let items = collection.containers[0]
+            .set
+            .items
+            .as_ref()
+            .expect("expected items");
+
+        let series = &items[0];
+        assert_eq!(series.title(), "The Mandalorian");
+        assert!(series.is_series());
+        assert_eq!(series.release_year(), Some(2019));
+        assert_eq!(series.ratings[0].value.to_string(), "TV-PG");
+        assert_eq!(series.tags[0].kind.as_deref(), Some("disneyPlusSeriesId"));
+        assert_eq!(series.current_availability.as_ref().and_then(|a| a.region.as_deref()), Some("US"));
+        assert_eq!(series.media_rights.as_ref().and_then(|rights| rights.download_blocked), Some(false));
+        assert!(series.content_id.is_some());
+
+        let movie = &items[1];
+        assert!(!movie.is_series());
+        assert_eq!(movie.program_type.as_deref(), Some("movie"));
+        assert_eq!(movie.video_art[0].purpose, "tile_ambient");
+    }
+
+    // Anything odd about the metadata shouldn't stop the item, or the home page, from parsing.
+    #[test]
+    fn tolerates_unexpected_metadata() {
+        let item = serde_json::json!({
+            "contentId": "not a uuid",
+            "currentAvailability": "US",
+            "ratings": [{ "system": "BBFC", "value": 12 }, { "nonsense": true }],
+            "releases": [{ "releaseYear": "1999" }],
+            "tags": 7,
+            "videoArt": [{}],
+            "text": {
+                "title": { "full": { "program": { "default": { "content": "Odd", "language": "en" } } } }
+            }
+        });
+
+        let item = serde_json::from_value::<Item>(item).expect("lenient item");
+        assert_eq!(item.content_id, None);
+        assert!(item.current_availability.is_none());
+        assert_eq!(item.ratings.len(), 1);
+        assert_eq!(item.ratings[0].value.as_u64(), Some(12));
+        assert_eq!(item.release_year(), Some(1999));
+        assert!(item.tags.is_empty());
+        assert!(item.video_art.is_empty());
+        assert!(item.image.tile.is_empty());
+    }
+
+    // Whatever was left out stays left out, and whatever we couldn't read goes back out as it came.
+    #[test]
+    fn round_trips_unexpected_metadata() {
+        let text = serde_json::json!({ "title": { "full": { "program": {
+            "default": { "content": "Odd", "language": "en" },
+            "x": 7
+        } } } });
+        let sparse = serde_json::json!({ "text": text });
+        let malformed = serde_json::json!({
+            "callToAction": null,
+            "contentId": "not a uuid",
+            "currentAvailability": { "region": "US", "kidsMode": null },
+            "image": {},
+            "mediaRights": "none",
+            "ratings": [{ "advisories": [], "system": "BBFC", "value": 12 }, { "nonsense": true }],
+            "releases": [{ "releaseDate": null, "releaseYear": "1999" }],
+            "seriesId": null,
+            "tags": 7,
+            "type": 5,
+            "videoArt": [],
+            "text": text
+        });
+
+        for source in &[sparse, malformed.clone()] {
+            let item = serde_json::from_value::<Item>(source.clone()).expect("item");
+            let json = serde_json::to_value(&item).expect("serialize item");
+            assert_eq!(json, *source);
+            assert_eq!(serde_json::from_value::<Item>(json).expect("reparse item"), item);
+            assert_eq!(item.text.title.full.text().extra["x"], 7);
+            assert!(item.content_id.is_none() && item.kind.is_none());
+        }
+
+        let item = serde_json::from_value::<Item>(malformed).expect("item");
+        assert_eq!(item.ratings.len(), 1);
+        assert_eq!(item.release_year(), Some(1999));
+        assert_eq!(item.extra["contentId"], "not a uuid");
+    }
+
+    // Images keep whatever is sent alongside their details too.
+    #[test]
+    fn image_round_trips() {
+        let source = serde_json::json!({ "series": {
+            "default": { "masterWidth": 500, "masterHeight": 281, "url": "https://example.com/a.jpg" },
+            "focalPoint": { "x": 0.5 }
+        } });
+        let image = serde_json::from_value::<Image>(source.clone()).expect("image");
+        assert_eq!(image.kind(), "series");
+        assert_eq!(image.details().master_width, 500);
+        assert_eq!(image.extra()["focalPoint"]["x"], 0.5);
+        assert_eq!(serde_json::to_value(&image).expect("serialize image"), source);
+
+        assert!(serde_json::from_value::<Image>(serde_json::json!({ "poster": source["series"] })).is_err());
+    }
+
+    // Neither should a bad item, or text of some new kind.
+    #[test]
+    fn tolerates_bad_items() {
+        let title = |kind: &str, content: &str| serde_json::json!({
+            "title": { "full": { kind: { "default": { "content": content, "language": "en" } } } }
+        });
+        let source = serde_json::json!({
+            "items": [
+                { "text": title("program", "Good") },
+                { "contentId": "no text" },
+                { "text": { "title": { "full": { "program": { "default": { "content": 7 } } } } } },
+                { "text": title("hologram", "New") },
+                { "text": { "title": { "full": { "hologram": [1, 2] } } } }
+            ],
+            "text": title("set", "Set")
+        });
+
+        let set = serde_json::from_value::<Set>(source.clone()).expect("lenient set");
+        let items = set.items.as_ref().expect("items");
+        let titles = items.iter().map(Item::title).collect::<Vec<_>>();
+        assert_eq!(titles, vec!["Good", "New", ""]);
+        assert_eq!(items[1].text.title.full.kind(), "hologram");
+        assert_eq!(serde_json::to_value(&set).expect("serialize set"), source);
+    }
+
+    // A new kind of set shouldn't take the whole page down with it.
+    #[test]
+    fn unknown_kinds() {
+        let refset = serde_json::json!({
+            "data": { "FancyNewSet": { "setId": "x", "text": { "title": { "full": { "set": { "default": {
+                "content": "Fancy", "language": "en" } } } } } } }
+        });
+        let refset = serde_json::from_value::<RefSet>(refset).expect("unknown set kind");
+        assert!(refset.data.is_unknown());
+        assert_eq!(refset.data.kind(), "FancyNewSet");
+        // Close enough to a set to show as one.
+        let set = refset.data.set().expect("generic set");
+        assert_eq!(set.text.title.full.details().content, "Fancy");
+
+        let odd = serde_json::from_value::<RefSet>(serde_json::json!({ "data": { "Carousel": [1, 2, 3] } }))
+            .expect("unknown set kind");
+        assert!(odd.data.set().is_none());
+        // Kept exactly as it was sent.
+        assert_eq!(
+            serde_json::to_value(&odd).expect("serialize refset"),
+            serde_json::json!({ "data": { "Carousel": [1, 2, 3] } })
+        );
+
+        let home = serde_json::from_value::<Home>(serde_json::json!({ "data": { "EditorialCollection": {} } }))
+            .expect("unknown home kind");
+        assert_eq!(home.data.kind(), "EditorialCollection");
+        assert!(home.data.collection().is_none());
+
+        // Known kinds still have to be right.
+        assert!(serde_json::from_value::<RefSet>(serde_json::json!({ "data": { "CuratedSet": {} } })).is_err());
+    }
+
+    // Re-serialising gives back exactly what was sent, including everything we don't model.
+    #[test]
+    fn round_trips() {
+        let source = serde_json::from_slice::<serde_json::Value>(&fixtures::fixture_bytes("home.json"))
+            .expect("home json");
+        let home = serde_json::from_value::<Home>(source.clone()).expect("home");
+        let json = serde_json::to_value(&home).expect("serialize home");
+        assert_eq!(json, source);
+        assert_eq!(serde_json::from_value::<Home>(json).expect("reparse home"), home);
+
+        let collection = home.data.collection().expect("collection");
+        assert!(collection.extra.contains_key("videoArt"));
+        let item = &collection.containers[0].set.items.as_ref().expect("expected items")[0];
+        assert_eq!(item.image.tile["1.78"].details().extra["masterId"].as_str().map(str::is_empty), Some(false));
+
+        for path in fixtures::refset_paths() {
+            let source = serde_json::from_slice::<serde_json::Value>(&fixtures::fixture_bytes(&path))
+                .expect("refset json");
+            let refset = serde_json::from_value::<RefSet>(source.clone()).expect("refset");
+            assert_eq!(serde_json::to_value(&refset).expect("serialize refset"), source);
+        }
+    }
+
+    #[test]
+    fn paging_meta() {
+        let home = fixtures::home();
+        let collection = home.data.collection().expect("collection");
+        let set = &collection.containers[0].set;
+        assert!(set.set_id.is_some());
+        let meta = set.meta.clone().expect("meta");
+        assert_eq!((meta.hits, meta.offset, meta.page_size), (3, 0, 15));
+        assert_eq!(meta.next_offset(), None);
+
+        let meta = Meta { hits: 31, offset: 15, ..meta };
+        assert_eq!(meta.next_offset(), Some(30));
+        assert_eq!(Meta { offset: 30, ..meta.clone() }.next_offset(), None);
+        assert_eq!(Meta { page_size: 0, ..meta }.next_offset(), None);
+    }
+
+    #[test]
+    fn language_fallback() {
+        let text = serde_json::from_value::<Text>(serde_json::json!({ "program": {
+            "default": { "content": "Soul", "language": "en" },
+            "fr-FR": { "content": "Soul (fr)", "language": "fr" },
+            "fr-CA": { "content": "Soul (fr-CA)", "language": "fr-CA" },
+            "es": { "content": "Soul (es)", "language": "es-419" },
+            "broken": 7
+        } }))
+        .expect("localized text");
+
+        let content = |languages: &[&str]| text.localized(&Languages::new(languages)).content.clone();
+        assert_eq!(content(&[]), "Soul");
+        assert_eq!(content(&["de", "FR-ca"]), "Soul (fr-CA)");
+        assert_eq!(content(&["fr-BE"]), "Soul (fr)");
+        // Exact matches only win within the same preference.
+        assert_eq!(content(&["es", "fr-CA"]), "Soul (es)");
+        assert_eq!(content(&["de", "ja"]), "Soul");
+        assert_eq!(text.details().content, "Soul");
+
+        let mut languages = Languages::parse("fr-CA, en,");
+        assert_eq!(languages.preferred(), ["fr-ca", "en"]);
+        languages.rotate();
+        assert_eq!(text.localized(&languages).content, "Soul");
+    }
+
     #[test]
     fn fetch_text() {
-        let home = fetch_home();
+        let home = fixtures::home();
 
-        let details = home.data.collection().containers[0]
+        let collection = home.data.collection().expect("collection");
+        let details = collection.containers[0]
             .set
             .text
             .title
             .full
             .details();
-        println!("{:?}", details.content);
+        assert_eq!(details.content, "New to Disney+");
     }
 }
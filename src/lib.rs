@@ -14,6 +14,11 @@ extern crate anyhow;
 extern crate image;
 
 pub mod app;
+pub mod bundle;
+pub mod catalogue;
+pub mod dump;
+#[cfg(test)]
+pub mod fixtures;
 pub mod grabber;
 pub mod home;
 pub mod menu;
@@ -2,13 +2,233 @@
 
 use mimalloc::MiMalloc;
 
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use dss::{
+    bundle::{self, BundleTransport},
+    dump::Dump,
+    grabber::{
+        Bandwidth, BearerToken, GrabberConfig, HostPattern, LogRequests, MiddlewareChain, MiddlewareTransport,
+        HttpGrabber, ReqwestTransport, RetryPolicy, SetHeader, ThrottledTransport,
+    },
+    home::Languages,
+    menu::menu::API_URL,
+};
+
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
 
+const USAGE: &str = "usage: dss [--capture <bundle directory> | --replay <bundle directory> | --dump <json|text> [<bundle directory>]]";
+
+enum DumpFormat {
+    Json,
+    Text,
+}
+
+enum Mode {
+    // Normal live api.
+    Run,
+    // Crawl everything into a bundle and exit without opening a window.
+    Capture(PathBuf),
+    // Serve only from a previously captured bundle.
+    Replay(PathBuf),
+    // Print the rows and tiles the menu would show without opening a window, from the live api or a
+    // bundle when given one.
+    Dump(DumpFormat, Option<PathBuf>),
+}
+
+fn parse_args() -> Result<Mode, String> {
+    let mut args = std::env::args().skip(1);
+    let mode = match args.next().as_deref() {
+        None => Mode::Run,
+        Some("--capture") => Mode::Capture(args.next().ok_or(USAGE)?.into()),
+        Some("--replay") => Mode::Replay(args.next().ok_or(USAGE)?.into()),
+        Some("--dump") => {
+            let format = match args.next().as_deref() {
+                Some("json") => DumpFormat::Json,
+                Some("text") => DumpFormat::Text,
+                _ => return Err(USAGE.to_owned()),
+            };
+            Mode::Dump(format, args.next().map(PathBuf::from))
+        }
+        Some(_) => return Err(USAGE.to_owned()),
+    };
+
+    match args.next() {
+        Some(_) => Err(USAGE.to_owned()),
+        None => Ok(mode),
+    }
+}
+
+// Staging needs auth and sometimes a proxy, all picked up from the environment so nothing secret has to
+// live in the repo. Auth only goes to the api host, never the image cdn.
+fn middleware_from_env() -> MiddlewareChain {
+    let api_host = reqwest::Url::parse(API_URL)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned))
+        .unwrap_or_default();
+
+    let mut middleware = MiddlewareChain::new();
+    if let Ok(device_id) = std::env::var("DSS_DEVICE_ID") {
+        middleware = middleware.with_host(&api_host, SetHeader::new("x-device-id", &device_id));
+    }
+    if let Ok(token) = std::env::var("DSS_BEARER_TOKEN") {
+        middleware = middleware.with_host(&api_host, BearerToken(token));
+    }
+    if std::env::var_os("DSS_LOG_REQUESTS").is_some() {
+        middleware = middleware.with(LogRequests);
+    }
+    middleware
+}
+
+fn network_transport() -> Result<ReqwestTransport, Box<dyn std::error::Error>> {
+    let transport = match std::env::var("DSS_PROXY") {
+        Ok(proxy) => ReqwestTransport::with_proxies(&[(HostPattern::Any, proxy)])?,
+        Err(_) => ReqwestTransport::new(),
+    };
+
+    match std::env::var("DSS_MAX_BANDWIDTH") {
+        Ok(kib_per_second) => {
+            let kib_per_second = kib_per_second.trim().parse::<u64>()?;
+            Ok(transport.with_bandwidth(Bandwidth::new(kib_per_second * 1024)))
+        }
+        Err(_) => Ok(transport),
+    }
+}
+
+// `DSS_LANGUAGES=fr-CA,fr,en` for titles in the first of those the api has, `L` cycles through them.
+fn languages() -> Languages {
+    std::env::var("DSS_LANGUAGES")
+        .map(|list| Languages::parse(&list))
+        .unwrap_or_default()
+}
+
+// `DSS_NO_PAGED_SETS=1` to stick with the first page of every set rather than loading the rest.
+fn paged_sets() -> bool {
+    std::env::var("DSS_NO_PAGED_SETS").as_deref() != Ok("1")
+}
+
+// `DSS_STATS_FILE=<path>` to write out grabber stats as json on exit.
+fn stats_file() -> Option<PathBuf> {
+    std::env::var_os("DSS_STATS_FILE").map(PathBuf::from)
+}
+
+// `DSS_SIMULATE_NETWORK=<KiB/s>[,<latency ms>]` to pretend to be on a slow connection.
+fn simulated_network() -> Result<Option<(Bandwidth, Duration)>, Box<dyn std::error::Error>> {
+    let setting = match std::env::var("DSS_SIMULATE_NETWORK") {
+        Ok(setting) => setting,
+        Err(_) => return Ok(None),
+    };
+
+    let mut parts = setting.splitn(2, ',');
+    let kib_per_second = parts.next().unwrap_or("").trim().parse::<u64>()?;
+    let latency = match parts.next() {
+        Some(latency) => latency.trim().parse::<u64>()?,
+        None => 0,
+    };
+
+    Ok(Some((Bandwidth::new(kib_per_second * 1024), Duration::from_millis(latency))))
+}
+
+// Applies any simulated network on top of `config`, the disk cache gets skipped so everything really
+// does come in slowly.
+fn simulate_network(mut config: GrabberConfig) -> Result<GrabberConfig, Box<dyn std::error::Error>> {
+    if let Some((bandwidth, latency)) = simulated_network()? {
+        println!(
+            "simulating {}KiB/s with {:?} latency",
+            bandwidth.bytes_per_second() / 1024,
+            latency
+        );
+        config.transport = Arc::new(ThrottledTransport::new(config.transport, latency, Some(bandwidth)));
+        config.disk_cache = None;
+    }
+    Ok(config)
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
-    dss::hide_console_window();
-    dss::app::App::new().await?.run()?;
+
+    match parse_args()? {
+        Mode::Run => {
+            dss::hide_console_window();
+            let config = simulate_network(GrabberConfig {
+                transport: Arc::new(network_transport()?),
+                middleware: middleware_from_env(),
+                stats_file: stats_file(),
+                ..Default::default()
+            })?;
+            dss::app::App::with_grabber_config(config)
+                .await?
+                .with_languages(languages())
+                .with_paging(paged_sets())
+                .run()?;
+        }
+        Mode::Capture(directory) => {
+            let transport = MiddlewareTransport::new(Arc::new(network_transport()?), middleware_from_env());
+            let manifest = bundle::capture(&transport, &RetryPolicy::default(), &directory, paged_sets()).await?;
+            println!(
+                "captured {} responses into {:?}, {} failed",
+                manifest.entries.len(),
+                directory,
+                manifest.failures.len()
+            );
+        }
+        Mode::Replay(directory) => {
+            let bundle = BundleTransport::open(directory)?;
+            let paging = bundle.manifest().pages;
+            let config = simulate_network(GrabberConfig {
+                transport: Arc::new(bundle),
+                disk_cache: None,
+                retry: RetryPolicy::never(),
+                stats_file: stats_file(),
+                ..Default::default()
+            })?;
+
+            dss::hide_console_window();
+            dss::app::App::with_grabber_config(config)
+                .await?
+                .with_languages(languages())
+                .with_paging(paging)
+                .run()?;
+        }
+        Mode::Dump(format, directory) => {
+            let (config, paging) = match directory {
+                Some(directory) => {
+                    let bundle = BundleTransport::open(directory)?;
+                    let paging = bundle.manifest().pages;
+                    let config = GrabberConfig {
+                        transport: Arc::new(bundle),
+                        disk_cache: None,
+                        retry: RetryPolicy::never(),
+                        ..Default::default()
+                    };
+                    (config, paging)
+                }
+                None => {
+                    let config = GrabberConfig {
+                        transport: Arc::new(network_transport()?),
+                        middleware: middleware_from_env(),
+                        ..Default::default()
+                    };
+                    (config, paged_sets())
+                }
+            };
+
+            let mut grabber = HttpGrabber::with_config(config);
+            let dump = Dump::load(&mut grabber, &languages(), paging).await;
+            match format {
+                DumpFormat::Json => println!("{}", serde_json::to_string_pretty(&dump)?),
+                DumpFormat::Text => print!("{}", dump.to_text()),
+            }
+
+            if let Some(failure) = dump.failure {
+                return Err(failure.into());
+            }
+        }
+    }
+
     Ok(())
 }
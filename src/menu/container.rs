@@ -1,31 +1,83 @@
 use anyhow::Result;
 use glam::{Vec2, Vec3};
-use image::EncodableLayout;
+use std::sync::Arc;
 use std::task::Poll as PollTask;
 use uuid::Uuid;
 use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
 
 use crate::{
-    grabber::HttpGrabber,
-    home::{Item, RefSet},
+    grabber::{Decode, HttpGrabber, Payload, Priority, RequestId},
+    home::{self, Item, Languages, Meta, RefSet, Set},
     renderer::Renderer,
 };
 
-use super::{prelude::*, Tile, ASPECT_RATIO};
+use super::{menu::API_URL, prelude::*, window_priority, RowLayout, Tile};
 
 pub const TILE_SPACING: f32 = 0.25 * SCALE;
-pub static ASPECT_RATIO_STRING: &'static str = "1.78";
+// Tiles that fit on screen from the focused one onwards.
+pub const VISIBLE_TILES: usize = 5;
+// Tiles either side of the visible ones to get ready before they scroll in.
+pub const PREFETCH_TILES: usize = 5;
+// Past this many tiles away from focus we don't bother fetching at all.
+pub const KEEP_TILES: usize = 30;
+// Ask for the next page of a set once focus is this close to the last tile loaded.
+pub const PAGE_AHEAD: usize = VISIBLE_TILES + PREFETCH_TILES;
+// Tile shapes a row goes with unless told otherwise, best first.
+pub const ASPECT_RATIOS: [f32; 4] = [1.78, 1.33, 1.0, 0.71];
+// Left edge of the first tile, the same for every row whatever shape its tiles are.
+pub const FIRST_TILE_LEFT: f32 = -0.14 * SCALE;
+// Top of the tiles, leaving room for the row title above them.
+pub const TILE_TOP: f32 = 0.5 * SCALE;
+
+pub fn refset_url(ref_id: &Uuid) -> String {
+    format!("{}/sets/{}.json", API_URL, ref_id.to_hyphenated())
+}
+
+// The static cdn sends the first page of a set whatever the query asks for, `add_page` notices that and
+// the row stops asking.
+pub fn set_page_url(set_id: &Uuid, offset: u32, page_size: u32) -> String {
+    format!("{}?offset={}&page_size={}", refset_url(set_id), offset, page_size)
+}
 
 #[derive(Debug, Clone)]
 pub struct Container {
     position: InterpPosition,
     title_text: Text,
+    // Every language the title came in, to pick from again when the language changes.
+    localized_title: Option<home::Text>,
+    languages: Languages,
     ref_id: Option<Uuid>,
     refset_loaded: bool,
+    refset_request: Option<(RequestId, Priority)>,
+    // Why the dynamic ref set couldn't be loaded, the container just stays empty.
+    refset_failure: Option<String>,
+    // Set the row's items came from and the last page of it loaded, for loading the rest as focus
+    // gets near the end.
+    set_id: Option<Uuid>,
+    meta: Option<Meta>,
+    // Items added to the row so far, whether or not they got a tile.
+    item_count: usize,
+    // Items the menu hasn't put in its catalogue yet, along with their position in the row.
+    unindexed: Vec<(usize, Arc<Item>)>,
+    // Whether to ask for later pages of the set at all, on unless turned off.
+    paging: bool,
+    page_request: Option<(RequestId, Priority)>,
+    // Why the next page couldn't be loaded, no more are asked for after that.
+    page_failure: Option<String>,
+    // Tile shapes the row would like, best first, and the one it went with once it had items to pick
+    // by. Every tile in the row is that shape.
+    aspect_ratios: Vec<f32>,
+    aspect_ratio: Option<f32>,
+    layout: RowLayout,
+    // Number in front of each tile when the layout ranks them.
+    rank_texts: Vec<Text>,
 
     pub tiles: Vec<Tile>,
     focused_tile: usize,
     focused: bool,
+    // How much the menu wants this row loaded, `None` when it is too far away.
+    row_priority: Option<Priority>,
+    scale_factor: f64,
 
     dirty_list: Vec<usize>,
 }
@@ -39,12 +91,29 @@ impl Container {
         let mut new_container = Self {
             position: InterpPosition::new(),
             title_text: title_text,
+            localized_title: None,
+            languages: Languages::default(),
             ref_id: ref_id,
             refset_loaded: false,
+            refset_request: None,
+            refset_failure: None,
+            set_id: None,
+            meta: None,
+            item_count: 0,
+            unindexed: Vec::new(),
+            paging: true,
+            page_request: None,
+            page_failure: None,
+            aspect_ratios: ASPECT_RATIOS.to_vec(),
+            aspect_ratio: None,
+            layout: RowLayout::default(),
+            rank_texts: Vec::new(),
 
             tiles: Vec::new(),
             focused_tile: 0,
             focused: false,
+            row_priority: Some(Priority::Background),
+            scale_factor: 1.0,
 
             dirty_list: Vec::new(),
         };
@@ -53,40 +122,171 @@ impl Container {
         new_container
     }
 
+    // Row for a container of the home page, with whatever items it came with.
+    pub fn from_home(container_ref: &home::Container) -> Self {
+        let set = &container_ref.set;
+        let mut container = Container::new(set.text.title.full.details().content.clone(), set.ref_id);
+        container.set_localized_title(set.text.title.full.clone());
+        container.set_layout(RowLayout::from_style(&container_ref.style));
+        container.add_set(set);
+        container
+    }
+
     pub fn add_items(&mut self, items: &Vec<Item>) {
+        // The best ratio any of the first items have art in, later pages have to fit in with it.
+        if self.aspect_ratio.is_none() && !items.is_empty() {
+            let found = self
+                .aspect_ratios
+                .iter()
+                .copied()
+                .find(|&ratio| items.iter().any(|item| item.image.tile_for(&[ratio]).is_some()));
+            self.aspect_ratio = Some(found.unwrap_or_else(|| self.aspect_ratio()));
+        }
+
+        let aspect_ratio = self.aspect_ratio();
         for item in items {
-            // Get images with the aspect ratio we want.
-            if let Some(image) = item.image.tile.get(ASPECT_RATIO_STRING) {
-                let image_details = image.details();
-                let title = item.text.title.full.details().content.clone();
-                let mut tile = Tile::new(title, image_details.clone());
-                tile.set_size(Vec2::new(1.78 * SCALE, 1.0 * SCALE));
-                self.push_tile(tile);
+            let item = Arc::new(item.clone());
+            let title = item.text.title.full.details().content.clone();
+            // Art in the shape we want, else the closest shape there is cropped down to fit, and only a
+            // title card when there's no art at all so nothing goes missing from the row.
+            let mut tile = match item.image.tile_for(&[aspect_ratio]) {
+                Some((_, image)) => Tile::new(title, image.details().clone()),
+                None => match item.image.closest_tile(aspect_ratio) {
+                    Some((image_ratio, image)) => {
+                        let mut tile = Tile::new(title, image.details().clone());
+                        tile.set_crop(image_ratio, aspect_ratio);
+                        tile
+                    }
+                    None => Tile::title_card(title),
+                },
+            };
+            tile.set_item(item.clone(), &self.languages);
+            self.push_tile(tile);
+
+            self.unindexed.push((self.item_count, item));
+            self.item_count += 1;
+        }
+    }
+
+    pub fn take_unindexed(&mut self) -> Vec<(usize, Arc<Item>)> {
+        std::mem::take(&mut self.unindexed)
+    }
+
+    // Tiles showing `item` switch over to `shared`, the catalogue's copy of the same title.
+    pub fn share_item(&mut self, item: &Arc<Item>, shared: &Arc<Item>) {
+        for tile in &mut self.tiles {
+            if tile.item().map_or(false, |tile_item| Arc::ptr_eq(tile_item, item)) {
+                tile.share_item(shared.clone());
             }
         }
     }
 
+    // Items of the set and where to carry on from for the next page.
+    pub fn add_set(&mut self, set: &Set) {
+        if let Some(items) = &set.items {
+            self.add_items(items);
+        }
+
+        self.set_id = set.set_id.or(self.ref_id);
+        self.meta = set.meta.clone();
+    }
+
     pub fn remove_tile(&mut self, tile_index: usize) {
         self.tiles.swap_remove(tile_index);
+        // Ranks go by place in the row, so it's the last one that goes.
+        self.rank_texts.truncate(self.tiles.len());
     }
 
     pub fn reset_tile_positions(&mut self) {
-        for (index, tile) in self.tiles.iter_mut().enumerate() {
-            tile.set_position(&Container::tile_position(index));
+        let size = self.tile_size();
+        for index in 0..self.tiles.len() {
+            let position = self.tile_position(index);
+            self.tiles[index].set_position(&position);
+            self.tiles[index].set_size(size);
         }
+
+        self.rank_texts = (0..self.tiles.len())
+            .filter(|_| self.layout.ranked())
+            .map(|index| self.new_rank_text(index))
+            .collect();
     }
 
-    pub fn tile_position(tile_index: usize) -> Vec3 {
-        Vec3::new( 
-            0.75 * SCALE + (ASPECT_RATIO * SCALE + TILE_SPACING) * tile_index as f32,
-            SCALE,
+    pub fn tile_position(&self, tile_index: usize) -> Vec3 {
+        let size = self.tile_size();
+        Vec3::new(
+            FIRST_TILE_LEFT + self.layout.rank_width() + 0.5 * size.x + self.tile_stride() * tile_index as f32,
+            TILE_TOP + 0.5 * size.y,
             0.0,
         )
     }
 
+    pub fn layout(&self) -> RowLayout {
+        self.layout
+    }
+
+    // Tiles already in the row are laid out again, but keep the aspect ratio they were picked with.
+    pub fn set_layout(&mut self, layout: RowLayout) {
+        self.layout = layout;
+        self.set_aspect_ratios(layout.aspect_ratios());
+        self.reset_tile_positions();
+    }
+
+    // Room the row takes up in the menu, the title lives in the spacing between rows.
+    pub fn row_height(&self) -> f32 {
+        self.layout.tile_height()
+    }
+
+    pub fn rank_text(&self, tile_index: usize) -> Option<&Text> {
+        self.rank_texts.get(tile_index)
+    }
+
+    // Big number filling the space in front of the tile.
+    fn new_rank_text(&self, tile_index: usize) -> Text {
+        let mut text = Text::new((tile_index + 1).to_string());
+        let left = FIRST_TILE_LEFT + self.tile_stride() * tile_index as f32;
+        text.set_position(&Vec3::new(left + 0.05 * SCALE, TILE_TOP, 0.0));
+        text.set_font_size(0.8 * self.layout.tile_height());
+        text.set_color([0.8, 0.8, 0.8, 1.0]);
+        text.set_parent_position(&self.absolute_position());
+        text
+    }
+
+    // Ratio the row went with, or the one it would like most while it has nothing to go by.
+    pub fn aspect_ratio(&self) -> f32 {
+        self.aspect_ratio
+            .or_else(|| self.aspect_ratios.first().copied())
+            .unwrap_or(ASPECT_RATIOS[0])
+    }
+
+    // Only picked again while the row is still empty, tiles already in it keep their shape.
+    pub fn set_aspect_ratios(&mut self, aspect_ratios: Vec<f32>) {
+        self.aspect_ratios = aspect_ratios;
+        if self.tiles.is_empty() {
+            self.aspect_ratio = None;
+        }
+    }
+
+    pub fn tile_size(&self) -> Vec2 {
+        let height = self.layout.tile_height();
+        Vec2::new(self.aspect_ratio() * height, height)
+    }
+
+    // Distance from one tile to the next.
+    pub fn tile_stride(&self) -> f32 {
+        self.tile_size().x + self.layout.tile_spacing() + self.layout.rank_width()
+    }
+
     pub fn construct_refset(&mut self, refset: &RefSet) {
-        if let Some(items) = &refset.data.set().items {
-            self.add_items(items);
+        match refset.data.set() {
+            Some(set) => {
+                if refset.data.is_unknown() {
+                    log::warn!("showing set of unknown kind {} as a plain set", refset.data.kind());
+                }
+
+                self.add_set(&set);
+            }
+            // Leave the row empty rather than failing anything else.
+            None => log::warn!("skipping set of unknown kind {}", refset.data.kind()),
         }
 
         self.refset_loaded = true;
@@ -94,10 +294,142 @@ impl Container {
 
     pub fn push_tile(&mut self, mut tile: Tile) {
         tile.set_parent_position(&self.absolute_position());
-        tile.set_position(&Container::tile_position(self.tiles.len()));
+        tile.set_position(&self.tile_position(self.tiles.len()));
+        tile.set_size(self.tile_size());
+        tile.set_priority(self.tile_priority(self.tiles.len()));
+        tile.set_scale_factor(self.scale_factor);
+        if self.layout.ranked() {
+            let rank_text = self.new_rank_text(self.tiles.len());
+            self.rank_texts.push(rank_text);
+        }
         self.tiles.push(tile);
     }
 
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+        for tile in &mut self.tiles {
+            tile.set_scale_factor(scale_factor);
+        }
+    }
+
+    pub fn set_row_priority(&mut self, row_priority: Option<Priority>) {
+        self.row_priority = row_priority;
+        self.update_tile_priorities();
+    }
+
+    pub fn row_priority(&self) -> Option<Priority> {
+        self.row_priority
+    }
+
+    // A tile is only as important as the row it is in.
+    pub fn tile_priority(&self, tile_index: usize) -> Option<Priority> {
+        let offset = tile_index as isize - self.focused_tile as isize;
+        let horizontal = window_priority(offset, VISIBLE_TILES, PREFETCH_TILES, KEEP_TILES);
+
+        match (self.row_priority, horizontal) {
+            (Some(row), Some(horizontal)) => Some(row.min(horizontal)),
+            _ => None,
+        }
+    }
+
+    pub fn update_tile_priorities(&mut self) {
+        for index in 0..self.tiles.len() {
+            let priority = self.tile_priority(index);
+            self.tiles[index].set_priority(priority);
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        self.title_text.text()
+    }
+
+    pub fn set_localized_title(&mut self, title: home::Text) {
+        self.localized_title = Some(title);
+        self.update_title();
+    }
+
+    // Picks the title of the row and every tile in it again.
+    pub fn set_languages(&mut self, languages: &Languages) {
+        self.languages = languages.clone();
+        self.update_title();
+        for tile in &mut self.tiles {
+            tile.set_languages(languages);
+        }
+    }
+
+    fn update_title(&mut self) {
+        if let Some(title) = &self.localized_title {
+            let title = title.localized(&self.languages).content.clone();
+            self.title_text.set_text(title);
+        }
+    }
+
+    pub fn ref_id(&self) -> Option<Uuid> {
+        self.ref_id
+    }
+
+    pub fn refset_failure(&self) -> Option<&String> {
+        self.refset_failure.as_ref()
+    }
+
+    pub fn paging(&self) -> bool {
+        self.paging
+    }
+
+    pub fn set_paging(&mut self, paging: bool) {
+        self.paging = paging;
+    }
+
+    pub fn page_failure(&self) -> Option<&String> {
+        self.page_failure.as_ref()
+    }
+
+    // Last page of the set loaded so far.
+    pub fn meta(&self) -> Option<&Meta> {
+        self.meta.as_ref()
+    }
+
+    // Items added to the row so far, whether or not they got a tile.
+    pub fn item_count(&self) -> usize {
+        self.item_count
+    }
+
+    // Next page of the set, refused when it isn't the one that was asked for since anything serving the
+    // same page again would have us asking for it forever. No more pages are asked for after that.
+    pub fn add_page(&mut self, page: &RefSet) -> Result<(), String> {
+        let offset = self.meta.as_ref().and_then(Meta::next_offset);
+        match page.data.set() {
+            Some(set) if set.meta.as_ref().map(|meta| meta.offset) == offset => {
+                if let Some(items) = &set.items {
+                    self.add_items(items);
+                }
+                self.meta = set.meta.clone();
+                Ok(())
+            }
+            _ => {
+                let failure = format!("expected a page at offset {:?}", offset);
+                self.page_failure = Some(failure.clone());
+                Err(failure)
+            }
+        }
+    }
+
+    // Url of the next page of the set, whether or not it is wanted yet.
+    pub fn next_page_url(&self) -> Option<String> {
+        if !self.paging || self.page_failure.is_some() {
+            return None;
+        }
+
+        let set_id = self.set_id?;
+        let meta = self.meta.as_ref()?;
+        Some(set_page_url(&set_id, meta.next_offset()?, meta.page_size))
+    }
+
+    // Focus is close enough to the last tile that the next page is wanted.
+    pub fn near_end(&self) -> bool {
+        self.focused_tile + PAGE_AHEAD >= self.tiles.len()
+    }
+
     pub fn focus(&mut self, focused: bool) {
         if let Some(tile) = self.tiles.get_mut(self.focused_tile) {
             tile.set_focus(focused);
@@ -114,6 +446,8 @@ impl Container {
         if let Some(tile) = self.tiles.get_mut(self.focused_tile) {
             tile.set_focus(true);
         }
+
+        self.update_tile_priorities();
     }
 }
 
@@ -139,6 +473,9 @@ impl PositionHierarchy for Container {
         for tile in &mut self.tiles {
             tile.set_parent_position(&absolute);
         }
+        for rank_text in &mut self.rank_texts {
+            rank_text.set_parent_position(&absolute);
+        }
     }
     fn set_position(&mut self, local_position: &Vec3) {
         self.position.set_position(local_position);
@@ -186,7 +523,7 @@ impl Input for Container {
                 self.focus_tile(new_focused_tile);
                 if let Some(_) = self.tiles.get(new_focused_tile) {
                     let mut position = self.position.wanted_position();
-                    position.x = 0.5 * SCALE + (ASPECT_RATIO * SCALE + TILE_SPACING) * new_focused_tile as f32 * -1.0;
+                    position.x = 0.5 * SCALE + self.tile_stride() * new_focused_tile as f32 * -1.0;
                     self.position.interp_position(position, 0.75);
                 }
 
@@ -203,31 +540,34 @@ impl Poll for Container {
     fn poll(&mut self, grabber: &mut HttpGrabber) -> Result<bool> {
         let mut done = true;
         for tile in &mut self.tiles {
-            done = done && tile.poll(grabber)?;
+            // Don't short circuit, every tile should get the chance to make progress.
+            let tile_done = tile.poll(grabber)?;
+            done = done && tile_done;
         }
 
         // poll for dynamic ref sets.
-        if !self.refset_loaded {
+        if !self.refset_loaded && self.refset_failure.is_none() {
             if let Some(ref_id) = self.ref_id {
-                let dynamic_refset = format!(
-                    "https://cd-static.bamgrid.com/dp-117731241344/sets/{}.json",
-                    ref_id.to_hyphenated().to_string()
-                );
+                let dynamic_refset = refset_url(&ref_id);
+                let request = track_request(grabber, &mut self.refset_request, &dynamic_refset, self.row_priority);
+
                 done = done
-                    && match grabber.poll_request(dynamic_refset.clone()) {
-                        Ok(PollTask::Pending) => false,
-                        Ok(PollTask::Ready(refset)) => {
-                            println!("got refset: {}", dynamic_refset);
-
-                            let refset = refset?;
-                            let refset = serde_json::from_slice(refset.as_bytes())?;
-                            self.construct_refset(&refset);
-                            false
-                        }
-                        Err(err) => {
-                            eprintln!("fetch dynamic refset: {:?}", err);
-                            false
-                        }
+                    && match request {
+                        Some(request) => self.take_refset(grabber, request, &dynamic_refset),
+                        None => false,
+                    };
+            }
+        }
+
+        if let Some(next_page) = self.next_page_url() {
+            // Once asked for, a page is seen through even if focus moves away again.
+            if self.page_request.is_some() || self.near_end() {
+                let request = track_request(grabber, &mut self.page_request, &next_page, self.row_priority);
+
+                done = done
+                    && match request {
+                        Some(request) => self.take_page(grabber, request, &next_page),
+                        None => false,
                     };
             }
         }
@@ -236,9 +576,97 @@ impl Poll for Container {
     }
 }
 
+// Keeps a request for `url` in line with how much it is wanted: submitted once there is a priority,
+// reprioritised as that changes, and cancelled once there isn't one.
+fn track_request(
+    grabber: &mut HttpGrabber,
+    tracked: &mut Option<(RequestId, Priority)>,
+    url: &str,
+    priority: Option<Priority>,
+) -> Option<RequestId> {
+    match (*tracked, priority) {
+        (Some((request, requested_priority)), Some(priority)) => {
+            if requested_priority != priority {
+                grabber.set_priority(request, priority);
+                *tracked = Some((request, priority));
+            }
+            Some(request)
+        }
+        (None, Some(priority)) => {
+            let request = grabber.submit_decoded(url.to_owned(), priority, Decode::RefSet);
+            *tracked = Some((request, priority));
+            Some(request)
+        }
+        (Some((request, _)), None) => {
+            grabber.cancel(request);
+            *tracked = None;
+            None
+        }
+        (None, None) => None,
+    }
+}
+
+impl Container {
+    // Returns true if the container is done polling.
+    fn take_page(&mut self, grabber: &mut HttpGrabber, request: RequestId, url: &str) -> bool {
+        match grabber.try_take(request) {
+            PollTask::Pending => false,
+            PollTask::Ready(page) => {
+                self.page_request = None;
+
+                let page = match page.and_then(Payload::into_refset) {
+                    Ok(page) => page,
+                    Err(err) => {
+                        eprintln!("failed to load page {}: {:?}", url, err);
+                        self.page_failure = Some(err.to_string());
+                        return true;
+                    }
+                };
+
+                match self.add_page(&page) {
+                    Ok(()) => {
+                        println!("got page: {}", url);
+                        false
+                    }
+                    Err(_) => {
+                        log::warn!("page {} isn't the next page of its set, not loading any more", url);
+                        true
+                    }
+                }
+            }
+        }
+    }
+
+    // Returns true if the container is done polling.
+    fn take_refset(&mut self, grabber: &mut HttpGrabber, request: RequestId, dynamic_refset: &str) -> bool {
+        match grabber.try_take(request) {
+            PollTask::Pending => false,
+            PollTask::Ready(refset) => {
+                println!("got refset: {}", dynamic_refset);
+                self.refset_request = None;
+
+                match refset.and_then(Payload::into_refset) {
+                    Ok(refset) => {
+                        self.construct_refset(&refset);
+                        false
+                    }
+                    Err(err) => {
+                        eprintln!("failed to load refset {}: {:?}", dynamic_refset, err);
+                        self.refset_failure = Some(err.to_string());
+                        true
+                    }
+                }
+            }
+        }
+    }
+}
+
 impl Draw for Container {
     fn set_render_details(&mut self, renderer: &mut Renderer) {
         self.title_text.set_render_details(renderer);
+        for rank_text in &mut self.rank_texts {
+            rank_text.set_render_details(renderer);
+        }
 
         for tile in &mut self.tiles {
             tile.set_render_details(renderer);
@@ -253,3 +681,116 @@ impl Draw for Container {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Container, TILE_SPACING};
+    use crate::fixtures;
+    use crate::menu::{layout::RANK_WIDTH, PositionHierarchy, RowLayout, SCALE};
+
+    #[test]
+    fn picks_aspect_ratios() {
+        use crate::menu::{Input, UpdateDelta};
+        use winit::event::{DeviceId, ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+
+        let home = fixtures::home();
+        let collection = home.data.collection().expect("collection");
+        let items = collection.containers[0].set.items.clone().expect("items");
+
+        // Luca only has poster art so it gets cropped to fit the default row.
+        let mut standard = Container::new("standard".to_owned(), None);
+        standard.add_items(&items);
+        assert_eq!(standard.aspect_ratio(), 1.78);
+        assert_eq!(standard.tiles.len(), 3);
+        assert_eq!(standard.tiles[1].crop_from(), None);
+        assert_eq!(standard.tiles[2].crop_from(), Some(0.71));
+        assert_eq!(standard.tile_position(1).x, 0.75 * SCALE + 1.78 * SCALE + TILE_SPACING);
+
+        let mut posters = Container::new("posters".to_owned(), None);
+        posters.set_aspect_ratios(vec![1.0, 0.71, 1.78]);
+        posters.add_items(&items);
+        assert_eq!(posters.aspect_ratio(), 0.71);
+        assert_eq!(posters.tiles.len(), 3);
+        assert_eq!(*posters.tiles[2].size(), glam::Vec2::new(0.71 * SCALE, SCALE));
+
+        // Lined up on the left with every other row, and spaced by their own width.
+        let left = |container: &Container, index: usize| {
+            container.tile_position(index).x - 0.5 * container.tile_size().x
+        };
+        assert!((left(&posters, 0) - left(&standard, 0)).abs() < 0.001);
+        assert!((left(&posters, 2) - left(&posters, 1) - (0.71 * SCALE + TILE_SPACING)).abs() < 0.001);
+
+        // Scrolling moves along one poster at a time.
+        #[allow(deprecated)]
+        let right = WindowEvent::KeyboardInput {
+            device_id: unsafe { DeviceId::dummy() },
+            input: KeyboardInput {
+                scancode: 0,
+                state: ElementState::Pressed,
+                virtual_keycode: Some(VirtualKeyCode::Right),
+                modifiers: Default::default(),
+            },
+            is_synthetic: false,
+        };
+        assert!(posters.input(&right));
+        // Exactly the length of the scroll, easing overshoots anything past that.
+        for _ in 0..3 {
+            posters.update_delta(0.25);
+        }
+        let scrolled = 0.5 * SCALE - posters.position().local_position().x;
+        assert!((scrolled - posters.tile_stride()).abs() < 0.001, "scrolled {}", scrolled);
+    }
+    // Numbered from one, with room in front of every tile for the number.
+    #[test]
+    fn numbers_trending_rows() {
+        let home = fixtures::home();
+        let collection = home.data.collection().expect("collection");
+        let mut trending = Container::new("trending".to_owned(), None);
+        trending.set_layout(RowLayout::Trending);
+        trending.add_items(collection.containers[0].set.items.as_ref().expect("items"));
+
+        assert_eq!(trending.rank_text(0).map(|text| text.text()), Some("1"));
+        assert_eq!(trending.rank_text(1).map(|text| text.text()), Some("2"));
+        assert_eq!(trending.rank_text(2).map(|text| text.text()), Some("3"));
+        assert!(trending.rank_text(3).is_none());
+        let standard_stride = 1.78 * SCALE + TILE_SPACING;
+        assert_eq!(trending.tile_stride(), standard_stride + RANK_WIDTH);
+    }
+    #[tokio::test]
+    async fn keeps_items_without_art() {
+        use crate::menu::Poll;
+        use std::sync::Arc;
+
+        let home = fixtures::home();
+        let collection = home.data.collection().expect("collection");
+        let mut items = collection.containers[0].set.items.clone().expect("items");
+        items[1].image.tile.remove("1.78");
+        items[2].image.tile.clear();
+
+        let mut container = Container::new("dummy".to_owned(), None);
+        container.add_items(&items);
+        assert_eq!(container.tiles.len(), 3);
+        assert_eq!(container.tiles[1].crop_from(), Some(0.71));
+        // Cropped art can't share a texture with the same image shown whole.
+        assert!(container.tiles[1].texture_key().ends_with("#crop1.780"));
+
+        let card = &container.tiles[2];
+        assert!(card.is_title_card());
+        assert_eq!(card.title(), "Luca");
+        assert_eq!(*card.size(), glam::Vec2::new(1.78 * SCALE, SCALE));
+        assert!(card.texture_image().is_some());
+
+        // Nothing to download for the card, and the poster comes back already cropped.
+        let transport = Arc::new(fixtures::memory_transport());
+        let mut grabber = fixtures::offline_grabber(transport.clone());
+        for _ in 0..1000 {
+            if container.poll(&mut grabber).expect("polling") {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+        assert_eq!(transport.requested().len(), 2);
+        let cropped = container.tiles[1].texture_image().expect("cropped poster");
+        assert_eq!(cropped.dimensions(), (2, 1));
+    }
+}
@@ -0,0 +1,102 @@
+use super::{
+    container::{ASPECT_RATIOS, TILE_SPACING},
+    SCALE,
+};
+
+// Room left in front of each tile of a ranked row for its number.
+pub const RANK_WIDTH: f32 = 0.6 * SCALE;
+
+// How a row lays out its tiles, picked by the style the home page gives its container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowLayout {
+    // Plain shelf of wide tiles, also what anything we don't know the style of gets.
+    Standard,
+    // Big banners across the top of the page.
+    Hero,
+    // Tall box art.
+    Poster,
+    // Small square logos.
+    Brand,
+    // Standard shelf with a big rank number in front of each tile.
+    Trending,
+}
+
+impl Default for RowLayout {
+    fn default() -> Self {
+        RowLayout::Standard
+    }
+}
+
+impl RowLayout {
+    // Styles are matched loosely since the api isn't consistent about them, `TrendingSet` and
+    // `trending` are both trending rows.
+    pub fn from_style(style: &str) -> Self {
+        let style = style.to_ascii_lowercase();
+        if style.starts_with("hero") {
+            RowLayout::Hero
+        } else if style.starts_with("poster") || style.starts_with("character") {
+            RowLayout::Poster
+        } else if style.starts_with("brand") {
+            RowLayout::Brand
+        } else if style.starts_with("trending") {
+            RowLayout::Trending
+        } else {
+            RowLayout::Standard
+        }
+    }
+
+    // Tile shapes the row would like, best first.
+    pub fn aspect_ratios(&self) -> Vec<f32> {
+        match self {
+            RowLayout::Standard | RowLayout::Trending => ASPECT_RATIOS.to_vec(),
+            RowLayout::Hero => vec![1.78, 1.33],
+            RowLayout::Poster => vec![0.71, 1.0, 1.33, 1.78],
+            RowLayout::Brand => vec![1.0, 1.33, 1.78],
+        }
+    }
+
+    // Every tile in the row is this tall, whatever its aspect ratio.
+    pub fn tile_height(&self) -> f32 {
+        match self {
+            RowLayout::Standard | RowLayout::Trending => SCALE,
+            RowLayout::Hero => 2.0 * SCALE,
+            RowLayout::Poster => 1.5 * SCALE,
+            RowLayout::Brand => 0.75 * SCALE,
+        }
+    }
+
+    // Gap between one tile and the next, not counting room for rank numbers.
+    pub fn tile_spacing(&self) -> f32 {
+        match self {
+            RowLayout::Brand => 0.15 * SCALE,
+            _ => TILE_SPACING,
+        }
+    }
+
+    pub fn ranked(&self) -> bool {
+        *self == RowLayout::Trending
+    }
+
+    // Room in front of every tile for its rank number, if it has one.
+    pub fn rank_width(&self) -> f32 {
+        if self.ranked() {
+            RANK_WIDTH
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RowLayout;
+
+    #[test]
+    fn layout_from_style() {
+        assert_eq!(RowLayout::from_style("hero"), RowLayout::Hero);
+        assert_eq!(RowLayout::from_style("brandSix"), RowLayout::Brand);
+        assert_eq!(RowLayout::from_style("TrendingSet"), RowLayout::Trending);
+        assert_eq!(RowLayout::from_style("editorial"), RowLayout::Standard);
+        assert_eq!(RowLayout::from_style("somethingNew"), RowLayout::Standard);
+    }
+}
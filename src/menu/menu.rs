@@ -1,15 +1,26 @@
 use anyhow::Result;
 use glam::{ Vec3};
-use image::EncodableLayout;
 use std::task::Poll as PollTask;
 use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
 
-use crate::{grabber::HttpGrabber, home::Home, renderer::Renderer};
+use crate::{
+    catalogue::Catalogue,
+    grabber::{Decode, HttpGrabber, Payload, Priority, RequestId},
+    home::{Home, Languages},
+    renderer::Renderer,
+};
 
-use super::{prelude::*, Container};
+use super::{prelude::*, window_priority, Container};
 
+pub static API_URL: &'static str = "https://cd-static.bamgrid.com/dp-117731241344";
 pub static HOME_URL: &'static str = "https://cd-static.bamgrid.com/dp-117731241344/home.json";
 pub const COLLECTION_SPACING: f32 = 0.75 * SCALE;
+// Rows that fit on screen from the focused one downwards.
+pub const VISIBLE_CONTAINERS: usize = 3;
+// Rows either side of the visible ones to get ready before they scroll in.
+pub const PREFETCH_CONTAINERS: usize = 2;
+// Past this many rows away from focus we don't bother fetching at all.
+pub const KEEP_CONTAINERS: usize = 8;
 
 #[derive(Debug, Clone)]
 pub struct Menu {
@@ -27,6 +38,18 @@ pub struct Menu {
     dirty_list: Vec<usize>,
 
     home_loaded: bool,
+    home_request: Option<RequestId>,
+    // Why the home page couldn't be loaded, nothing else gets polled when this is set.
+    home_failure: Option<String>,
+
+    // Window scale factor, tiles need it to figure out what image size to ask for.
+    scale_factor: f64,
+    // What language to show titles in, when the api has them in more than one.
+    languages: Languages,
+    // Whether rows can load later pages of their sets, see `set_page_url`.
+    paging: bool,
+    // Everything the rows have loaded so far, by content id.
+    catalogue: Catalogue,
 }
 
 impl Menu {
@@ -41,32 +64,106 @@ impl Menu {
             dirty_list: Vec::new(),
 
             home_loaded: false,
+            home_request: None,
+            home_failure: None,
+
+            scale_factor: 1.0,
+            languages: Languages::default(),
+            paging: true,
+            catalogue: Catalogue::new(),
+        }
+    }
+
+    pub fn catalogue(&self) -> &Catalogue {
+        &self.catalogue
+    }
+
+    // Picks up whatever the rows have added since last time.
+    fn index_new_items(&mut self) {
+        for (row, container) in self.containers.iter_mut().enumerate() {
+            for (position, item) in container.take_unindexed() {
+                if let Some(shared) = self.catalogue.add_shared_item(row, position, &item) {
+                    container.share_item(&item, &shared);
+                }
+            }
+        }
+    }
+
+    pub fn languages(&self) -> &Languages {
+        &self.languages
+    }
+
+    // Titles already showing switch over in place.
+    pub fn set_languages(&mut self, languages: Languages) {
+        for container in &mut self.containers {
+            container.set_languages(&languages);
+        }
+        self.languages = languages;
+    }
+
+    pub fn set_paging(&mut self, paging: bool) {
+        self.paging = paging;
+        for container in &mut self.containers {
+            container.set_paging(paging);
+        }
+    }
+
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+        for container in &mut self.containers {
+            container.set_scale_factor(scale_factor);
         }
     }
 
     pub fn push_container(&mut self, mut container: Container) {
         container.set_parent_position(&self.absolute_position());
-        container.set_position(&Vec3::new(
-            0.5 * SCALE,
-            (SCALE + COLLECTION_SPACING) * self.containers.len() as f32,
-            0.0,
-        ));
+        container.set_position(&Vec3::new(0.5 * SCALE, self.row_offset(self.containers.len()), 0.0));
+        container.set_row_priority(self.row_priority(self.containers.len()));
+        container.set_scale_factor(self.scale_factor);
+        container.set_languages(&self.languages);
+        container.set_paging(self.paging);
         self.containers.push(container);
     }
 
-    pub fn construct_home(&mut self, home: &Home) {
-        let mut new_containers = Vec::new();
+    // How far down the menu a row starts, rows are as tall as their layout makes them.
+    pub fn row_offset(&self, container_index: usize) -> f32 {
+        self.containers
+            .iter()
+            .take(container_index)
+            .map(|container| container.row_height() + COLLECTION_SPACING)
+            .sum()
+    }
 
-        for container_ref in &home.data.collection().containers {
-            let text_details = container_ref.set.text.title.full.details();
-            let mut container =
-                Container::new(text_details.content.clone(), container_ref.set.ref_id);
+    pub fn row_priority(&self, container_index: usize) -> Option<Priority> {
+        let offset = container_index as isize - self.focused_container as isize;
+        window_priority(offset, VISIBLE_CONTAINERS, PREFETCH_CONTAINERS, KEEP_CONTAINERS)
+    }
+
+    pub fn update_row_priorities(&mut self) {
+        for index in 0..self.containers.len() {
+            let priority = self.row_priority(index);
+            self.containers[index].set_row_priority(priority);
+        }
+    }
 
-            if let Some(items) = &container_ref.set.items {
-                container.add_items(items);
+    pub fn construct_home(&mut self, home: &Home) {
+        let collection = match home.data.collection() {
+            Some(collection) => collection,
+            None => {
+                log::warn!("can't show home page of unknown kind {}", home.data.kind());
+                self.home_failure = Some(format!("unsupported home page kind {}", home.data.kind()));
+                return;
             }
+        };
 
-            new_containers.push(container);
+        if home.data.is_unknown() {
+            log::warn!("showing home page of unknown kind {} as a standard collection", home.data.kind());
+        }
+
+        let mut new_containers = Vec::new();
+
+        for container_ref in &collection.containers {
+            new_containers.push(Container::from_home(container_ref));
         }
 
         for new_container in new_containers {
@@ -76,6 +173,10 @@ impl Menu {
         self.home_loaded = true;
     }
 
+    pub fn home_failure(&self) -> Option<&String> {
+        self.home_failure.as_ref()
+    }
+
     pub fn focus_container(&mut self, container_index: usize) {
         if let Some(container) = self.containers.get_mut(self.focused_container) {
             container.focus(false);
@@ -86,6 +187,8 @@ impl Menu {
         if let Some(container) = self.containers.get_mut(self.focused_container) {
             container.focus(true);
         }
+
+        self.update_row_priorities();
     }
 }
 
@@ -140,6 +243,22 @@ impl Input for Menu {
                 }
                 return true;
             }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::L),
+                        ..
+                    },
+                ..
+            } => {
+                // Next preferred language.
+                let mut languages = self.languages.clone();
+                languages.rotate();
+                println!("languages: {:?}", languages.preferred());
+                self.set_languages(languages);
+                return true;
+            }
             WindowEvent::KeyboardInput {
                 input:
                     KeyboardInput {
@@ -189,7 +308,7 @@ impl Input for Menu {
                 self.focus_container(new_focused_container);
                 if let Some(_) = self.containers.get(new_focused_container) {
                     let mut position = self.position.wanted_position();
-                    position.y = new_focused_container as f32 * (SCALE + COLLECTION_SPACING) * -1.0;
+                    position.y = self.row_offset(new_focused_container) * -1.0;
                     self.position.interp_position(position, 0.75);
                 }
 
@@ -211,20 +330,43 @@ impl Poll for Menu {
         if self.home_loaded {
             let mut done = true;
             for container in &mut self.containers {
-                done = done && container.poll(grabber)?;
+                // Don't short circuit, every container should get the chance to make progress.
+                let container_done = container.poll(grabber)?;
+                done = done && container_done;
             }
+            self.index_new_items();
 
             Ok(done)
+        } else if self.home_failure.is_some() {
+            Ok(true)
         } else {
-            match grabber.poll_request(HOME_URL.to_owned())? {
+            let request = match self.home_request {
+                Some(request) => request,
+                None => {
+                    let request = grabber.submit_decoded(HOME_URL.to_owned(), Priority::Focused, Decode::Home);
+                    self.home_request = Some(request);
+                    request
+                }
+            };
+
+            match grabber.try_take(request) {
                 PollTask::Pending => Ok(false),
                 PollTask::Ready(home) => {
                     println!("got homepage, rendering page now");
+                    self.home_request = None;
+
                     // Construct initial homepage.
-                    let home = home?;
-                    let home = serde_json::from_slice(home.as_bytes())?;
-                    self.construct_home(&home);
-                    Ok(false)
+                    match home.and_then(Payload::into_home) {
+                        Ok(home) => {
+                            self.construct_home(&home);
+                            Ok(self.home_failure.is_some())
+                        }
+                        Err(err) => {
+                            eprintln!("failed to load home {}: {:?}", HOME_URL, err);
+                            self.home_failure = Some(err.to_string());
+                            Ok(true)
+                        }
+                    }
                 }
             }
         }
@@ -264,9 +406,10 @@ impl Draw for Menu {
 
 #[cfg(test)]
 mod test {
-    use crate::home::ImageDetails;
+    use crate::grabber::Priority;
+    use crate::home::{ImageDetails, Languages};
     use crate::menu::{Container, Menu, PositionHierarchy, Tile};
-    use glam::Vec3;
+    use glam::{ Vec3};
 
     #[test]
     fn hierarchy_test() {
@@ -274,6 +417,7 @@ mod test {
             master_width: 0,
             master_height: 0,
             url: "dummy".to_owned(),
+            extra: Default::default(),
         };
 
         let mut menu = Menu::new();
@@ -319,4 +463,304 @@ mod test {
         println!("{:?}", menu.containers[1].absolute_position());
         println!("{:?}", menu.containers[1].tiles[0].absolute_position());
     }
+
+    #[test]
+    fn priorities_follow_focus() {
+        let dummy_details: ImageDetails = ImageDetails {
+            master_width: 0,
+            master_height: 0,
+            url: "dummy".to_owned(),
+            extra: Default::default(),
+        };
+
+        let mut menu = Menu::new();
+        for _ in 0..12 {
+            let mut container = Container::new("dummy".to_owned(), None);
+            for _ in 0..40 {
+                container.push_tile(Tile::new("dummy".to_owned(), dummy_details.clone()));
+            }
+            menu.push_container(container);
+        }
+
+        let priority = |menu: &Menu, container: usize, tile: usize| {
+            menu.containers[container].tiles[tile].priority()
+        };
+
+        assert_eq!(priority(&menu, 0, 0), Some(Priority::Focused));
+        assert_eq!(priority(&menu, 0, 4), Some(Priority::Visible));
+        assert_eq!(priority(&menu, 0, 7), Some(Priority::Prefetch));
+        assert_eq!(priority(&menu, 0, 20), Some(Priority::Background));
+        assert_eq!(priority(&menu, 0, 35), None);
+        assert_eq!(priority(&menu, 1, 0), Some(Priority::Visible));
+        assert_eq!(priority(&menu, 3, 0), Some(Priority::Prefetch));
+        assert_eq!(priority(&menu, 6, 0), Some(Priority::Background));
+        assert_eq!(priority(&menu, 9, 0), None);
+
+        menu.focus_container(9);
+        menu.containers[9].focus_tile(30);
+        assert_eq!(priority(&menu, 9, 30), Some(Priority::Focused));
+        assert_eq!(priority(&menu, 9, 0), Some(Priority::Background));
+        assert_eq!(priority(&menu, 0, 0), None);
+        assert_eq!(priority(&menu, 7, 0), Some(Priority::Prefetch));
+    }
+
+    #[test]
+    fn lays_out_rows_by_style() {
+        use crate::fixtures;
+        use crate::home::Home;
+        use crate::menu::{menu::COLLECTION_SPACING, RowLayout, SCALE};
+
+        let json = String::from_utf8(fixtures::fixture_bytes("home.json").to_vec())
+            .expect("utf8")
+            .replace(r#""style": "editorial""#, r#""style": "hero""#)
+            .replace(r#""style": "BecauseYouSet""#, r#""style": "poster""#);
+        let home: Home = serde_json::from_str(&json).expect("home");
+
+        let mut menu = Menu::new();
+        menu.construct_home(&home);
+        let layouts = menu.containers.iter().map(Container::layout).collect::<Vec<_>>();
+        assert_eq!(layouts, vec![RowLayout::Hero, RowLayout::Poster, RowLayout::Trending]);
+
+        // Rows below the hero are pushed down by how tall it is.
+        assert_eq!(menu.row_offset(1), 2.0 * SCALE + COLLECTION_SPACING);
+        assert_eq!(menu.row_offset(2), 3.5 * SCALE + 2.0 * COLLECTION_SPACING);
+        assert_eq!(menu.containers[2].absolute_position().y, menu.row_offset(2));
+
+        let hero = &menu.containers[0];
+        assert_eq!(hero.tiles.len(), 3);
+        assert_eq!(*hero.tiles[0].size(), glam::Vec2::new(1.78 * 2.0 * SCALE, 2.0 * SCALE));
+        assert!(hero.rank_text(0).is_none());
+    }
+
+    async fn poll_until_done(menu: &mut Menu, grabber: &mut crate::grabber::HttpGrabber) -> bool {
+        use crate::menu::Poll;
+
+        for _ in 0..1000 {
+            if menu.poll(grabber).expect("polling") {
+                return true;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+        false
+    }
+
+    // Whole home page loads through the grabber without touching the network, and a missing image
+    // only fails its own tile.
+    #[tokio::test]
+    async fn loads_offline() {
+        use crate::fixtures;
+        use crate::grabber::{strip_query, TransportResponse};
+        use std::sync::Arc;
+
+        let missing = fixtures::image_urls()[0].clone();
+        let mut transport = fixtures::memory_transport();
+        transport.insert_response(strip_query(&missing), TransportResponse::not_found());
+        let mut grabber = fixtures::offline_grabber(Arc::new(transport));
+
+        let mut menu = Menu::new();
+        assert!(poll_until_done(&mut menu, &mut grabber).await, "menu never finished polling");
+        assert!(menu.home_failure().is_none());
+
+        let tile_counts = menu
+            .containers
+            .iter()
+            .map(|container| container.tiles.len())
+            .collect::<Vec<_>>();
+        assert_eq!(tile_counts, vec![3, 3, 2]);
+
+        for tile in menu
+            .containers
+            .iter()
+            .flat_map(|container| container.tiles.iter())
+        {
+            if tile.details().url == missing {
+                assert!(tile.failure().is_some());
+                assert!(tile.texture_image().is_none());
+            } else {
+                assert!(tile.failure().is_none());
+                assert!(tile.texture_image().is_some());
+            }
+        }
+
+        // The same titles in different rows are one catalogue entry, and their tiles draw the same texture.
+        assert_eq!(menu.catalogue().len(), 6);
+        let mandalorian = menu.containers[0].tiles[0].content_id().expect("content id");
+        assert_eq!(menu.catalogue().rows_for(&mandalorian), vec![0, 2]);
+        let duplicate = menu.containers[2]
+            .tiles
+            .iter()
+            .find(|tile| tile.content_id() == Some(mandalorian))
+            .expect("duplicate tile");
+        assert_eq!(duplicate.texture_key(), menu.containers[0].tiles[0].texture_key());
+        let shared = menu.catalogue().get(&mandalorian).expect("entry").item.clone();
+        assert!(Arc::ptr_eq(duplicate.item().expect("item"), &shared));
+        assert!(Arc::ptr_eq(menu.containers[0].tiles[0].item().expect("item"), &shared));
+
+        let stats = grabber.stats();
+        assert_eq!(stats.by_kind["home"].requests, 1);
+        assert_eq!(stats.by_kind["refset"].requests, 2);
+        // Only the missing image fails, possibly more than once as it gets focused.
+        assert!(stats.by_kind["image"].failed > 0);
+        assert_eq!(stats.by_kind["image"].errors["status_4xx"], stats.overall.failed);
+        assert_eq!(stats.overall.cache.disabled, stats.overall.requests);
+        let home = grabber.request_metrics(super::HOME_URL).expect("home metrics");
+        assert_eq!(home.bytes, fixtures::fixture_bytes("home.json").len() as u64);
+        assert!(home.time_to_first_byte.is_some());
+    }
+
+    // Sets and home pages of a kind we don't know about get skipped instead of failing everything.
+    #[tokio::test]
+    async fn skips_unknown_kinds() {
+        use crate::fixtures;
+        use crate::menu::container::refset_url;
+        use std::sync::Arc;
+
+        let ref_id = uuid::Uuid::parse_str("bd1bfb9a-bbf7-43a0-ac5e-3e3889d7224d").expect("ref id");
+        let mut transport = fixtures::memory_transport();
+        transport.insert(&refset_url(&ref_id), &br#"{ "data": { "Carousel": [] } }"#[..]);
+        let mut grabber = fixtures::offline_grabber(Arc::new(transport));
+
+        let mut menu = Menu::new();
+        assert!(poll_until_done(&mut menu, &mut grabber).await);
+        assert!(menu.home_failure().is_none());
+
+        let skipped = menu
+            .containers
+            .iter()
+            .find(|container| container.ref_id() == Some(ref_id))
+            .expect("row for unknown set");
+        assert!(skipped.tiles.is_empty());
+        assert!(skipped.refset_failure().is_none());
+        assert_eq!(menu.containers.iter().filter(|container| !container.tiles.is_empty()).count(), 2);
+
+        let mut transport = fixtures::memory_transport();
+        transport.insert(super::HOME_URL, &br#"{ "data": { "Carousel": [] } }"#[..]);
+        let mut grabber = fixtures::offline_grabber(Arc::new(transport));
+
+        let mut menu = Menu::new();
+        assert!(poll_until_done(&mut menu, &mut grabber).await);
+        assert_eq!(menu.home_failure().map(String::as_str), Some("unsupported home page kind Carousel"));
+    }
+
+    // Rows keep loading pages of their set while there are more hits, and stop at anything that isn't
+    // the page asked for. Nothing is asked for at all with paging off.
+    #[tokio::test]
+    async fn loads_more_pages() {
+        use crate::fixtures::{self, PagedTransport};
+        use crate::menu::container::{refset_url, set_page_url};
+        use std::sync::Arc;
+
+        let ref_id = uuid::Uuid::parse_str("bd1bfb9a-bbf7-43a0-ac5e-3e3889d7224d").expect("ref id");
+        let paged_row = |menu: &Menu| {
+            menu.containers
+                .iter()
+                .find(|container| container.ref_id() == Some(ref_id))
+                .expect("paged row")
+                .clone()
+        };
+
+        let transport = Arc::new(PagedTransport::new(fixtures::memory_transport(), &ref_id, 8, 3));
+        let mut grabber = fixtures::offline_grabber(transport.clone());
+        let mut menu = Menu::new();
+        assert!(poll_until_done(&mut menu, &mut grabber).await);
+        let paged = paged_row(&menu);
+        let content_ids = paged.tiles.iter().map(|tile| tile.content_id()).collect::<Vec<_>>();
+        let expected = (0..8).map(|position| Some(transport.content_id(position))).collect::<Vec<_>>();
+        assert_eq!(content_ids, expected);
+        assert!(paged.page_failure().is_none());
+        assert_eq!(paged.next_page_url(), None);
+        let requested = transport.requested();
+        assert!(requested.contains(&set_page_url(&ref_id, 3, 3)));
+        assert!(requested.contains(&set_page_url(&ref_id, 6, 3)));
+
+        // With paging turned off the row sticks with the first page and never asks.
+        let transport = Arc::new(PagedTransport::new(fixtures::memory_transport(), &ref_id, 8, 3));
+        let mut grabber = fixtures::offline_grabber(transport.clone());
+        let mut menu = Menu::new();
+        menu.set_paging(false);
+        assert!(poll_until_done(&mut menu, &mut grabber).await);
+        assert_eq!(paged_row(&menu).tiles.len(), 3);
+        assert_eq!(paged_row(&menu).next_page_url(), None);
+        assert!(!transport.requested().iter().any(|url| url.contains("offset=")));
+
+        // A server that ignores the offset just sends the first page again.
+        let first_page = transport.page(0, 3);
+        let mut transport = fixtures::memory_transport();
+        transport.insert(&refset_url(&ref_id), first_page);
+        let mut grabber = fixtures::offline_grabber(Arc::new(transport));
+        let mut menu = Menu::new();
+        assert!(poll_until_done(&mut menu, &mut grabber).await);
+        assert_eq!(paged_row(&menu).tiles.len(), 3);
+        assert!(paged_row(&menu).page_failure().is_some());
+    }
+
+    // Switching language retitles rows and tiles that are already showing.
+    #[tokio::test]
+    async fn switches_language() {
+        use crate::fixtures;
+        use std::sync::Arc;
+
+        let mut home = serde_json::from_slice::<serde_json::Value>(&fixtures::fixture_bytes("home.json")).expect("home");
+        let set = &mut home["data"]["StandardCollection"]["containers"][0]["set"];
+        set["text"]["title"]["full"]["set"]["fr"] = serde_json::json!({ "content": "Nouveau", "language": "fr" });
+        set["items"][0]["text"]["title"]["full"]["series"]["fr"] =
+            serde_json::json!({ "content": "Le Mandalorien", "language": "fr-FR" });
+
+        let mut transport = fixtures::memory_transport();
+        transport.insert(super::HOME_URL, serde_json::to_vec(&home).expect("home json"));
+        let mut grabber = fixtures::offline_grabber(Arc::new(transport));
+
+        let mut menu = Menu::new();
+        menu.set_languages(Languages::parse("fr,en"));
+        assert!(poll_until_done(&mut menu, &mut grabber).await);
+        assert_eq!(menu.containers[0].title(), "Nouveau");
+        assert_eq!(menu.containers[0].tiles[0].title(), "Le Mandalorien");
+        // Nothing in french, so the default.
+        assert_eq!(menu.containers[0].tiles[1].title(), "Soul");
+
+        let mut languages = menu.languages().clone();
+        languages.rotate();
+        menu.set_languages(languages);
+        assert_eq!(menu.containers[0].title(), "New to Disney+");
+        assert_eq!(menu.containers[0].tiles[0].title(), "The Mandalorian");
+    }
+
+    // Tiles only ask for the image size they are drawn at, and ask for a bigger one once focused or
+    // the scale factor goes up.
+    #[tokio::test]
+    async fn requests_sized_images() {
+        use crate::fixtures;
+        use std::sync::Arc;
+
+        let transport = Arc::new(fixtures::memory_transport());
+        let mut grabber = fixtures::offline_grabber(transport.clone());
+
+        let mut menu = Menu::new();
+        assert!(poll_until_done(&mut menu, &mut grabber).await);
+
+        let widths = |menu: &Menu| {
+            menu.containers[1]
+                .tiles
+                .iter()
+                .map(|tile| tile.texture_width())
+                .collect::<Vec<_>>()
+        };
+        // 356 logical pixels wide rounded up to the next step.
+        assert_eq!(widths(&menu), vec![384, 384, 384]);
+        assert!(transport.requested().iter().any(|url| url.ends_with("&width=384")));
+        assert!(!transport.requested().iter().any(|url| url.ends_with("&width=500")));
+
+        menu.containers[1].focus_tile(1);
+        assert!(poll_until_done(&mut menu, &mut grabber).await);
+        assert_eq!(widths(&menu), vec![384, 448, 384]);
+
+        menu.set_scale_factor(2.0);
+        assert!(poll_until_done(&mut menu, &mut grabber).await);
+        assert_eq!(widths(&menu), vec![768, 896, 768]);
+
+        // Never past the size of the master image.
+        menu.set_scale_factor(8.0);
+        assert!(poll_until_done(&mut menu, &mut grabber).await);
+        assert_eq!(widths(&menu), vec![1920, 1920, 1920]);
+    }
 }
@@ -1,7 +1,7 @@
-pub const ASPECT_RATIO: f32 = 1.78;
 pub const SCALE: f32 = 200.0;
 
 pub mod container;
+pub mod layout;
 pub mod menu;
 pub mod position;
 pub mod prelude;
@@ -9,17 +9,36 @@ pub mod text;
 pub mod tile;
 
 pub use container::Container;
+pub use layout::RowLayout;
 pub use menu::Menu;
 pub use position::{InterpPosition, Position, PositionHierarchy};
 pub use text::Text;
 pub use tile::Tile;
 
 use crate::{
-    grabber::HttpGrabber,
+    grabber::{HttpGrabber, Priority},
 };
 use anyhow::Result;
 use winit::event::WindowEvent;
 
+// Priority of something `offset` steps away from whatever is focused, where `visible` things from
+// the focus onwards fit on screen. `None` means it is far enough away to not bother fetching.
+pub fn window_priority(offset: isize, visible: usize, prefetch: usize, keep: usize) -> Option<Priority> {
+    let (visible, prefetch, keep) = (visible as isize, prefetch as isize, keep as isize);
+
+    if offset == 0 {
+        Some(Priority::Focused)
+    } else if offset > 0 && offset < visible {
+        Some(Priority::Visible)
+    } else if offset >= -prefetch && offset < visible + prefetch {
+        Some(Priority::Prefetch)
+    } else if offset.abs() <= keep {
+        Some(Priority::Background)
+    } else {
+        None
+    }
+}
+
 pub trait Input {
     // Pass along events to the UI elements.
     //
@@ -40,6 +40,10 @@ impl Text {
         self.update = true;
     }
 
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
     pub fn set_text(&mut self, text: String) {
         self.set_update();
         self.text = text;
@@ -1,17 +1,25 @@
 use anyhow::Result;
 use glam::{Vec2, Vec3};
-use image::EncodableLayout;
+use image::RgbaImage;
+use std::sync::Arc;
 use std::task::Poll as PollTask;
+use uuid::Uuid;
 use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
 
 use crate::{
-    grabber::HttpGrabber,
-    home::ImageDetails,
+    grabber::{strip_query, Decode, DownloadProgress, GrabError, HttpGrabber, Payload, Priority, RequestId},
+    home::{ImageDetails, Item, Languages},
     menu::prelude::*,
-    renderer::{Renderer, SpriteId, SpriteInstance, Texture},
+    renderer::{RenderContext, Renderer, SpriteId, SpriteInstance, Texture},
     util::EaseMethod,
 };
 
+// How much bigger the focused tile is drawn.
+pub const FOCUS_SCALE: f32 = 1.2;
+// Requested image widths get rounded up to a multiple of this so slightly different sizes share responses.
+pub const IMAGE_WIDTH_STEP: u32 = 64;
+pub const PROGRESS_BAR_HEIGHT: f32 = 0.03 * SCALE;
+
 #[derive(Debug, Clone)]
 pub struct Tile {
     position: Position,
@@ -19,11 +27,44 @@ pub struct Tile {
     focused: bool,
 
     title: String,
+    // What the tile shows, its title is picked from again when the language changes. Shared with the
+    // menu's catalogue once it's indexed, so the same title in several rows is only held once.
+    item: Option<Arc<Item>>,
 
     sprite: Option<SpriteId>,
+    // Shown along the bottom of the empty slot while the first image downloads.
+    progress_sprite: Option<SpriteId>,
+    // How much of the first image has come in, `None` when unknown or not downloading.
+    progress: Option<f32>,
     fallback_text: Option<Text>,
-    texture_bytes: Option<bytes::Bytes>,
+    // Why the image couldn't be shown, under the title.
+    fallback_reason: Option<Text>,
+    // Decoded by the grabber, just needs uploading.
+    // Shared with any other tile showing the same image.
+    texture_image: Option<Arc<RgbaImage>>,
+    // Width that was asked for when grabbing `texture_image`.
+    texture_width: u32,
+    // New bytes that haven't made it onto the existing sprite yet.
+    texture_dirty: bool,
+    // Sprite draws from the texture shared under `texture_key` rather than one of its own.
+    texture_shared: bool,
+    // Tiles showing the same image, like the same title in two rows, draw it from one texture.
+    texture_key: String,
+    // Aspect ratio of art in another shape than the tile and the one it gets cropped down to fit.
+    crop: Option<(f32, f32)>,
+    // No art at all, just a generated card with the title on it.
+    title_card: bool,
     details: ImageDetails,
+    // Physical pixels per logical pixel of whatever window we end up drawn in.
+    scale_factor: f64,
+    // Request along with the image width it asked for.
+    request: Option<(RequestId, Priority, u32)>,
+    // `None` when the tile is too far away to be worth fetching.
+    priority: Option<Priority>,
+    // Set once the grabber has given up on the image, we just show the title instead.
+    failure: Option<GrabError>,
+    // Largest width worth asking for, lowered when a higher resolution upgrade fails.
+    max_width: u32,
 
     counter: f64,
     duration: f64,
@@ -38,17 +79,106 @@ impl Tile {
             focused: false,
 
             title: title,
+            item: None,
             fallback_text: None,
+            fallback_reason: None,
 
             sprite: None,
-            texture_bytes: None,
+            progress_sprite: None,
+            progress: None,
+            texture_image: None,
+            texture_width: 0,
+            texture_dirty: false,
+            texture_shared: false,
+            // The width is left out since any size of the image will do until a bigger one comes in.
+            texture_key: strip_query(&details.url).to_owned(),
+            crop: None,
+            title_card: false,
+            max_width: details.master_width,
             details: details,
+            scale_factor: 1.0,
+            request: None,
+            priority: Some(Priority::Background),
+            failure: None,
             counter: 0.0,
             duration: 0.0,
             alpha: 0.0,
         }
     }
 
+    // Stands in for an item without any art, a gradient with the title over it.
+    pub fn title_card(title: String) -> Self {
+        let details = ImageDetails {
+            master_width: 0,
+            master_height: 0,
+            url: String::new(),
+            extra: Default::default(),
+        };
+
+        let mut tile = Self::new(title, details);
+        tile.texture_key = format!("title-card:{}", tile.title);
+        tile.texture_image = Some(Arc::new(title_card_image(&tile.title)));
+        tile.title_card = true;
+        tile.duration = 1.0;
+        tile.update_title_card_text();
+        tile
+    }
+
+    pub fn is_title_card(&self) -> bool {
+        self.title_card
+    }
+
+    pub fn crop_from(&self) -> Option<f32> {
+        self.crop.map(|(from, _)| from)
+    }
+
+    // The image is `from` rather than `to`, the shape of the tile, so only the middle of it is shown.
+    // The grabber does the cropping while decoding.
+    pub fn set_crop(&mut self, from: f32, to: f32) {
+        self.crop = Some((from, to));
+        self.texture_key = format!("{}#crop{:.3}", strip_query(&self.details.url), to);
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn set_title(&mut self, title: String) {
+        if let Some(fallback_text) = &mut self.fallback_text {
+            fallback_text.set_text(title.clone());
+        }
+        self.title = title;
+    }
+
+    pub fn set_item(&mut self, item: Arc<Item>, languages: &Languages) {
+        self.item = Some(item);
+        self.set_languages(languages);
+    }
+
+    // Swaps in another copy of the same item, like the catalogue's.
+    pub fn share_item(&mut self, item: Arc<Item>) {
+        self.item = Some(item);
+    }
+
+    pub fn item(&self) -> Option<&Arc<Item>> {
+        self.item.as_ref()
+    }
+
+    pub fn set_languages(&mut self, languages: &Languages) {
+        if let Some(item) = &self.item {
+            let title = item.text.title.full.localized(languages).content.clone();
+            self.set_title(title);
+        }
+    }
+
+    pub fn content_id(&self) -> Option<Uuid> {
+        self.item.as_ref()?.content_id
+    }
+
+    pub fn texture_key(&self) -> &str {
+        &self.texture_key
+    }
+
     pub fn position(&self) -> &Position {
         &self.position
     }
@@ -63,17 +193,87 @@ impl Tile {
 
     pub fn set_size(&mut self, size: Vec2) {
         self.size = size;
+        if self.title_card {
+            self.update_title_card_text();
+        }
     }
 
     pub fn set_focus(&mut self, focus: bool) {
         self.focused = focus;
     }
 
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+    }
+
+    pub fn set_priority(&mut self, priority: Option<Priority>) {
+        self.priority = priority;
+    }
+
+    pub fn priority(&self) -> Option<Priority> {
+        self.priority
+    }
+
+    pub fn details(&self) -> &ImageDetails {
+        &self.details
+    }
+
+    pub fn texture_image(&self) -> Option<&RgbaImage> {
+        self.texture_image.as_deref()
+    }
+
+    pub fn failure(&self) -> Option<&GrabError> {
+        self.failure.as_ref()
+    }
+
+    // Image width in physical pixels needed to draw the tile at its current size without upscaling.
+    pub fn wanted_width(&self) -> u32 {
+        let focus_scale = if self.focused { FOCUS_SCALE } else { 1.0 };
+        // Art wider than the tile loses its sides, so it needs to be that much wider to make up for it.
+        let crop_scale = match self.crop {
+            Some((from, to)) => (from / to).max(1.0),
+            None => 1.0,
+        };
+        let pixels = (self.size.x * focus_scale * crop_scale) as f64 * self.scale_factor;
+        let steps = (pixels / IMAGE_WIDTH_STEP as f64).ceil().max(1.0) as u32;
+        let width = steps * IMAGE_WIDTH_STEP;
+
+        // No point asking the api to scale past the master.
+        match self.max_width {
+            0 => width,
+            max_width => width.min(max_width),
+        }
+    }
+
+    pub fn texture_width(&self) -> u32 {
+        self.texture_width
+    }
+
+    pub fn progress(&self) -> Option<f32> {
+        self.progress
+    }
+
+    // Bar growing from the left along the bottom edge of the tile.
+    pub fn progress_instance(&self, fraction: f32) -> SpriteInstance {
+        let tile = self.focused_instance();
+        let width = tile.size[0] * fraction.clamp(0.0, 1.0);
+
+        SpriteInstance {
+            size: [width, PROGRESS_BAR_HEIGHT],
+            position: [
+                tile.position[0] - (tile.size[0] - width) / 2.0,
+                tile.position[1] + (tile.size[1] - PROGRESS_BAR_HEIGHT) / 2.0,
+                tile.position[2] + 0.5,
+            ],
+            alpha: 0.8,
+        }
+    }
+
     pub fn focused_instance(&self) -> SpriteInstance {
         let mut size = self.size;
         let mut position = self.absolute_position();
         if self.focused {
-            let focused_scaling = Vec2::new(1.2, 1.2);
+            let focused_scaling = Vec2::new(FOCUS_SCALE, FOCUS_SCALE);
             size = size * focused_scaling;
             position.z += 1.0;
         }
@@ -97,17 +297,84 @@ impl UpdateDelta for Tile {
 
 impl Poll for Tile {
     fn poll(&mut self, grabber: &mut HttpGrabber) -> Result<bool> {
-        match &self.texture_bytes {
-            Some(_) => Ok(true),
+        let wanted_width = self.wanted_width();
+        if self.title_card
+            || self.failure.is_some()
+            || (self.texture_image.is_some() && self.texture_width >= wanted_width)
+        {
+            return Ok(true);
+        }
+
+        let priority = match self.priority {
+            Some(priority) => priority,
+            None => {
+                // Scrolled far enough away that the bandwidth is better spent elsewhere.
+                if let Some((request, _, _)) = self.request.take() {
+                    grabber.cancel(request);
+                }
+                return Ok(false);
+            }
+        };
+
+        if let Some((request, _, width)) = self.request {
+            if width < wanted_width {
+                // Got focused or the scale factor changed since asking, so this one is already too small.
+                grabber.cancel(request);
+                self.request = None;
+            }
+        }
+
+        let (request, width) = match self.request {
+            Some((request, requested_priority, width)) => {
+                if requested_priority != priority {
+                    grabber.set_priority(request, priority);
+                    self.request = Some((request, priority, width));
+                }
+                (request, width)
+            }
             None => {
-                if let PollTask::Ready(bytes) = grabber.poll_request(self.details.url.clone())? {
-                    self.texture_bytes = Some(bytes?.clone());
-                    self.counter = 0.0;
-                    self.duration = 1.0;
-                    Ok(true)
-                } else {
-                    Ok(false)
+                let url = self.details.sized_url(wanted_width);
+                let decode = self.crop.map_or(Decode::Image, |(_, to)| Decode::cropped_image(to));
+                let request = grabber.submit_decoded(url, priority, decode);
+                self.request = Some((request, priority, wanted_width));
+                (request, wanted_width)
+            }
+        };
+
+        match grabber.try_take(request) {
+            PollTask::Pending => {
+                // Upgrades happen behind the image we already have, nothing to show for those.
+                if self.texture_image.is_none() {
+                    self.progress = grabber.progress(request).and_then(DownloadProgress::fraction);
                 }
+                Ok(false)
+            }
+            PollTask::Ready(response) => {
+                self.request = None;
+                self.progress = None;
+                match response.and_then(Payload::into_image) {
+                    Ok(image) => {
+                        // Only fade in the first time, upgrades just swap in place.
+                        if self.texture_image.is_none() {
+                            self.counter = 0.0;
+                            self.duration = 1.0;
+                        }
+                        self.texture_image = Some(image);
+                        self.texture_width = width;
+                        self.texture_dirty = true;
+                    }
+                    Err(err) if self.texture_image.is_some() => {
+                        // Still have the smaller image to show, just stop trying to upgrade it.
+                        eprintln!("failed to upgrade {} to width {}: {}", self.details.url, width, err);
+                        self.max_width = self.texture_width;
+                    }
+                    Err(err) => {
+                        // Grabber already retried, so this tile is just done.
+                        self.failure = Some(err);
+                    }
+                }
+
+                Ok(true)
             }
         }
     }
@@ -125,6 +392,9 @@ impl PositionHierarchy for Tile {
         if let Some(fallback_text) = &mut self.fallback_text {
             fallback_text.set_parent_position(&position);
         }
+        if let Some(fallback_reason) = &mut self.fallback_reason {
+            fallback_reason.set_parent_position(&position);
+        }
     }
 }
 
@@ -151,46 +421,227 @@ impl Input for Tile {
 impl Draw for Tile {
     fn set_render_details(&mut self, renderer: &mut Renderer) {
         let focused_instance = self.focused_instance();
+        self.set_progress_render_details(renderer);
 
-        match (&self.sprite, &self.texture_bytes, &mut self.fallback_text) {
-            (Some(sprite), _, fallback_text) => {
-                renderer
-                    .sprite_pass
-                    .set_sprite_instance(*sprite, focused_instance);
+        if let Some(sprite) = self.sprite {
+            renderer
+                .sprite_pass
+                .set_sprite_instance(sprite, focused_instance);
+
+            if self.texture_dirty {
+                self.texture_dirty = false;
+                let Renderer {
+                    sprite_pass,
+                    context,
+                    ..
+                } = renderer;
 
-                if let Some(fallback_text) = fallback_text {
-                    fallback_text.set_render_details(renderer);
+                if self.texture_shared {
+                    sprite_pass.share_texture(context.device(), self.texture_key(), self.texture_width, || {
+                        self.texture(context)
+                    });
+                } else if let Some(texture) = self.texture(context) {
+                    sprite_pass.set_sprite_texture(context.device(), sprite, texture);
                 }
             }
-            (None, Some(texture_bytes), _) => {
-                let texture = match Texture::from_bytes(
-                    &renderer.context().device(),
-                    &renderer.context().queue(),
-                    texture_bytes.as_bytes(),
-                    "test.jpeg",
-                ) {
-                    Ok(texture) => texture,
-                    Err(err) => {
-                        eprintln!("failed to fetch texture, err: {:?}", err);
-                        let mut text = Text::new(self.title.clone());
-                        text.set_position(&Vec3::new(-0.5 * SCALE, 0.0, 1.0)); // Arbitrary Z value but just so it goes over focused tile.
 
-                        self.fallback_text = Some(text);
-                        renderer.sprite_pass.fallback_texture(renderer.context())
-                    }
-                };
+            if let Some(fallback_text) = &mut self.fallback_text {
+                fallback_text.set_render_details(renderer);
+            }
+            if let Some(fallback_reason) = &mut self.fallback_reason {
+                fallback_reason.set_render_details(renderer);
+            }
 
+            return;
+        }
+
+        // Some other tile might have uploaded the same image already.
+        let shared = match self.texture_image {
+            Some(_) => {
                 let Renderer {
                     sprite_pass,
                     context,
                     ..
-                } = renderer;
-                let image_handle = sprite_pass.add_texture(context.device(), texture);
-                let instance_handle = sprite_pass.add_instance(focused_instance);
+                } = &mut *renderer;
+                sprite_pass.share_texture(context.device(), self.texture_key(), self.texture_width, || {
+                    self.texture(context)
+                })
+            }
+            None => None,
+        };
 
-                self.sprite = Some(sprite_pass.add_sprite(image_handle, instance_handle));
+        let image_handle = match (shared, &self.failure) {
+            (Some(image_handle), _) => {
+                self.texture_shared = true;
+                image_handle
+            }
+            (None, Some(failure)) => {
+                eprintln!("failed to fetch texture, err: {}", failure);
+                let texture = self.fallback(renderer);
+                renderer.sprite_pass.add_texture(renderer.context.device(), texture)
+            }
+            (None, None) if self.texture_image.is_some() => {
+                let texture = self.fallback(renderer);
+                renderer.sprite_pass.add_texture(renderer.context.device(), texture)
+            }
+            (None, None) => return,
+        };
+        self.texture_dirty = false;
+
+        let sprite_pass = &mut renderer.sprite_pass;
+        let instance_handle = sprite_pass.add_instance(focused_instance);
+
+        self.sprite = Some(sprite_pass.add_sprite(image_handle, instance_handle));
+        self.set_progress_render_details(renderer);
+    }
+}
+
+impl Tile {
+    fn set_progress_render_details(&mut self, renderer: &mut Renderer) {
+        let instance = match self.progress {
+            Some(fraction) if self.sprite.is_none() => self.progress_instance(fraction),
+            _ => match self.progress_sprite.take() {
+                // Nothing to take the sprite back out of the pass with, so just hide it.
+                Some(progress_sprite) => {
+                    renderer.sprite_pass.set_sprite_instance(
+                        progress_sprite,
+                        SpriteInstance {
+                            size: [0.0, 0.0],
+                            position: [0.0, 0.0, 0.0],
+                            alpha: 0.0,
+                        },
+                    );
+                    return;
+                }
+                None => return,
+            },
+        };
+
+        match self.progress_sprite {
+            Some(progress_sprite) => renderer
+                .sprite_pass
+                .set_sprite_instance(progress_sprite, instance),
+            None => {
+                let sprite_pass = &mut renderer.sprite_pass;
+                let instance_handle = sprite_pass.add_instance(instance);
+                self.progress_sprite = Some(sprite_pass.add_sprite(sprite_pass.solid_texture(), instance_handle));
+            }
+        }
+    }
+}
+
+impl Tile {
+    // Upload whatever image we have, `None` if there isn't one or the upload fails.
+    fn texture(&self, context: &RenderContext) -> Option<Texture> {
+        let texture_image = self.texture_image.as_ref()?;
+        match Texture::from_rgba(
+            context.device(),
+            context.queue(),
+            texture_image,
+            Some(&self.details.url),
+        ) {
+            Ok(texture) => Some(texture),
+            Err(err) => {
+                eprintln!("failed to fetch texture, err: {:?}", err);
+                None
             }
-            _ => {}
         }
     }
 }
+
+impl Tile {
+    // Title along the bottom left of the card, kept there as the tile changes size.
+    fn update_title_card_text(&mut self) {
+        let mut text = Text::new(self.title.clone());
+        text.set_position(&Vec3::new(-0.5 * self.size.x + 0.1 * SCALE, 0.5 * self.size.y - 0.3 * SCALE, 1.0));
+        text.set_font_size(28.0);
+        self.fallback_text = Some(text);
+        self.set_child_positions();
+    }
+
+    // Show the title, and why if we know, over the fallback image when we can't show the real one.
+    fn fallback(&mut self, renderer: &Renderer) -> Texture {
+        let mut text = Text::new(self.title.clone());
+        text.set_position(&Vec3::new(-0.5 * SCALE, 0.0, 1.0)); // Arbitrary Z value but just so it goes over focused tile.
+
+        self.fallback_text = Some(text);
+
+        self.fallback_reason = self.failure.as_ref().map(|failure| {
+            let mut reason = Text::new(failure.to_string());
+            reason.set_position(&Vec3::new(-0.5 * SCALE, 0.25 * SCALE, 1.0));
+            reason.set_font_size(18.0);
+            reason.set_color([1.0, 0.6, 0.6, 1.0]);
+            reason
+        });
+        self.set_child_positions();
+
+        renderer.sprite_pass.fallback_texture(renderer.context())
+    }
+}
+
+// Diagonal gradient in a colour picked from the title, so cards next to each other look different but
+// the same title always looks the same. Small, since it gets stretched smoothly over the tile anyway.
+pub fn title_card_image(title: &str) -> RgbaImage {
+    let hash = title.bytes().fold(5381u32, |hash, byte| hash.wrapping_mul(33) ^ byte as u32);
+    let hue = (hash % 360) as f32;
+    let light = hsv_to_rgb(hue, 0.6, 0.55);
+    let dark = hsv_to_rgb((hue + 40.0) % 360.0, 0.7, 0.15);
+
+    const SIZE: u32 = 32;
+    RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+        let t = (x + y) as f32 / (2 * (SIZE - 1)) as f32;
+        let mix = |channel: usize| (light[channel] + (dark[channel] - light[channel]) * t) * 255.0;
+        image::Rgba([mix(0) as u8, mix(1) as u8, mix(2) as u8, 255])
+    })
+}
+
+fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [f32; 3] {
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    [r + m, g + m, b + m]
+}
+
+#[cfg(test)]
+mod test {
+    use super::{title_card_image, Tile};
+    use crate::home::ImageDetails;
+
+    // Cropped art can't share a texture with the same image shown whole.
+    #[test]
+    fn crops_keyed_by_shape() {
+        let details = ImageDetails {
+            master_width: 500,
+            master_height: 704,
+            url: "https://example.com/luca.jpg?width=500".to_owned(),
+            extra: Default::default(),
+        };
+
+        let mut tile = Tile::new("Luca".to_owned(), details);
+        assert_eq!(tile.crop_from(), None);
+        assert_eq!(tile.texture_key(), "https://example.com/luca.jpg");
+
+        tile.set_crop(0.71, 1.78);
+        assert_eq!(tile.crop_from(), Some(0.71));
+        assert_eq!(tile.texture_key(), "https://example.com/luca.jpg#crop1.780");
+    }
+
+    // Nothing to download for a title card, and the same title always looks the same.
+    #[test]
+    fn title_cards() {
+        let card = Tile::title_card("Luca".to_owned());
+        assert!(card.is_title_card());
+        assert_eq!(card.title(), "Luca");
+        assert!(card.texture_image().is_some());
+        assert_eq!(title_card_image("Luca"), title_card_image("Luca"));
+        assert_ne!(title_card_image("Luca"), title_card_image("Soul"));
+    }
+}
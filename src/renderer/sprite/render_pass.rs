@@ -1,5 +1,7 @@
 use anyhow::Result;
 
+use std::collections::HashMap;
+
 use super::{sprite, SpriteInstance, SpriteMesh, SpriteTexture};
 use crate::{
     renderer::{RenderContext, Texture, Vertex},
@@ -50,6 +52,11 @@ pub struct SpritePass {
     texture_bind_group_layout: wgpu::BindGroupLayout,
 
     textures: ReuseVec<SpriteTexture>,
+    // Plain white texture any sprite can share for flat shapes like progress bars.
+    solid_texture: SpriteTextureId,
+    // Textures any number of sprites draw from, by whatever the caller knows the image as, along with
+    // the width that was uploaded.
+    shared_textures: HashMap<String, (SpriteTextureId, u32)>,
     instances: ManagedBuffer<SpriteInstance, SpriteInstanceId>,
     mesh: SpriteMesh,
 
@@ -166,12 +173,23 @@ impl SpritePass {
             wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
         );
 
+        let mut textures = ReuseVec::new();
+        let white = image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255]));
+        let solid_texture = Texture::from_rgba(context.device(), context.queue(), &white, Some("SpritePass::solid_texture"))?;
+        let solid_texture = SpriteTextureId(textures.push(SpritePass::bind_sprite_texture(
+            context.device(),
+            &texture_bind_group_layout,
+            solid_texture,
+        )));
+
         Ok(Self {
             pipeline,
 
             texture_bind_group_layout,
 
-            textures: ReuseVec::new(),
+            textures,
+            solid_texture,
+            shared_textures: HashMap::new(),
             mesh: sprite_mesh,
             instances: sprite_instances,
 
@@ -268,6 +286,36 @@ impl SpritePass {
         }
     }
 
+    pub fn solid_texture(&self) -> SpriteTextureId {
+        self.solid_texture
+    }
+
+    // Texture shared under `key`, uploading it with `texture` first if there isn't one yet or the one
+    // there is narrower than `width`. Replacing it in place means every sprite sharing it gets the bigger
+    // one. `None` if it needed uploading and `texture` couldn't.
+    pub fn share_texture<F>(&mut self, device: &wgpu::Device, key: &str, width: u32, texture: F) -> Option<SpriteTextureId>
+    where
+        F: FnOnce() -> Option<Texture>,
+    {
+        if let Some((id, shared_width)) = self.shared_textures.get(key).copied() {
+            if width > shared_width {
+                if let Some(texture) = texture() {
+                    let sprite_texture =
+                        SpritePass::bind_sprite_texture(device, &self.texture_bind_group_layout, texture);
+                    if let Some(existing) = self.textures.get_mut(id.id()) {
+                        *existing = sprite_texture;
+                    }
+                    self.shared_textures.insert(key.to_owned(), (id, width));
+                }
+            }
+            return Some(id);
+        }
+
+        let id = self.add_texture(device, texture()?);
+        self.shared_textures.insert(key.to_owned(), (id, width));
+        Some(id)
+    }
+
     pub fn add_instance(&mut self, instance: SpriteInstance) -> SpriteInstanceId {
         self.instances.push(instance)
     }
@@ -288,6 +336,18 @@ impl SpritePass {
         }
     }
 
+    // Swap out what a sprite draws, like when a higher resolution image comes in.
+    pub fn set_sprite_texture(&mut self, device: &wgpu::Device, handle: SpriteId, texture: Texture) {
+        let sprite = self.sprites.get(handle.0).cloned();
+        if let Some(sprite) = sprite {
+            let sprite_texture =
+                SpritePass::bind_sprite_texture(device, &self.texture_bind_group_layout, texture);
+            if let Some(existing) = self.textures.get_mut(sprite.texture.id()) {
+                *existing = sprite_texture;
+            }
+        }
+    }
+
     pub fn fallback_texture(&self, context: &RenderContext) -> Texture {
         let fallback_bytes = include_bytes!("./fallback.png");
         let fallback_texture = Texture::from_bytes(&context.device(), &context.queue(), fallback_bytes, "fallback.png")
@@ -1,5 +1,4 @@
 use anyhow::Result;
-use image::GenericImageView;
 
 // Texture and sampler for usage in shaders.
 pub struct Texture {
@@ -38,8 +37,17 @@ impl Texture {
         img: &image::DynamicImage,
         label: Option<&str>,
     ) -> Result<Self> {
-        let rgba = img.to_rgba8();
-        let dimensions = img.dimensions();
+        Self::from_rgba(device, queue, &img.to_rgba8(), label)
+    }
+
+    // Only uploads, so decoding can happen somewhere else beforehand.
+    pub fn from_rgba(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rgba: &image::RgbaImage,
+        label: Option<&str>,
+    ) -> Result<Self> {
+        let dimensions = rgba.dimensions();
 
         let size = wgpu::Extent3d {
             width: dimensions.0,